@@ -1,50 +1,227 @@
 use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const CONFIG_ENV: &str = "INSPIRE_CONFIG";
+const CONFIG_FILE: &str = "config.toml";
 
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     styles: Vec<StyleConfig>,
+    wrappers: Vec<WrapperConfig>,
+    pub daily: DailyConfig,
+    pub history: HistoryConfig,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             styles: vec![StyleConfig::default()],
+            wrappers: Vec::new(),
+            daily: DailyConfig::default(),
+            history: HistoryConfig::default(),
         }
     }
 }
 
-use nu_ansi_term::{Color, Style};
+/// Controls which day `--daily` considers it to be.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DailyConfig {
+    /// An IANA timezone name such as `Europe/London`, `UTC` or `local`
+    pub timezone: String,
+    /// The hour of the day at which the quote changes
+    pub rollover_hour: u32,
+    /// Mixed into the date so that different teams can have different quotes
+    pub seed: Option<String>,
+}
 
-#[derive(Copy, Clone, Serialize, Deserialize, PartialEq)]
-pub enum Alignment {
-    Left,
-    Center,
-    Right,
-    MiddleRight,
+impl Default for DailyConfig {
+    fn default() -> Self {
+        Self {
+            timezone: String::from("local"),
+            rollover_hour: 0,
+            seed: None,
+        }
+    }
 }
 
-impl From<pad::Alignment> for Alignment {
-    fn from(value: pad::Alignment) -> Self {
-        match value {
-            pad::Alignment::Left => Alignment::Left,
-            pad::Alignment::Right => Alignment::Right,
-            pad::Alignment::Middle => Alignment::Center,
-            pad::Alignment::MiddleRight => Alignment::MiddleRight,
+impl Config {
+    /// Loads the config file, preferring an explicit path, then `INSPIRE_CONFIG`, then
+    /// `inspire/config.toml` in the user's config directory. Only the last is optional.
+    pub fn load(explicit: Option<&Path>) -> Result<Self, ConfigError> {
+        let path = match explicit {
+            Some(path) => path.to_path_buf(),
+            None => match env::var_os(CONFIG_ENV) {
+                Some(path) => PathBuf::from(path),
+                None => match Self::default_path().filter(|p| p.is_file()) {
+                    Some(path) => path,
+                    None => return Ok(Self::default()),
+                },
+            },
+        };
+
+        Self::read(&path)
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("inspire").join(CONFIG_FILE))
+    }
+
+    pub fn read(path: &Path) -> Result<Self, ConfigError> {
+        let content =
+            fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let mut config: Self =
+            toml::from_str(&content).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+
+        // A config which only defines custom styles still gets the built in default
+        if !config.styles.iter().any(|s| s.name == "default") {
+            config.styles.push(StyleConfig::default());
         }
+
+        Ok(config)
+    }
+
+    pub fn get_style(&self, name: &str) -> Result<&StyleConfig, ConfigError> {
+        self.styles
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| ConfigError::UnknownStyle {
+                name: name.to_owned(),
+                available: self.styles.iter().map(|s| s.name.clone()).collect(),
+            })
+    }
+
+    pub fn get_wrapper(&self, name: &str) -> Option<&WrapperConfig> {
+        self.wrappers.iter().find(|w| w.name == name)
     }
 }
 
-impl Into<pad::Alignment> for Alignment {
-    fn into(self) -> pad::Alignment {
+/// Controls how inspire avoids showing the same quote twice.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// How many recently shown quotes to avoid, zero disables the history
+    pub length: usize,
+    /// Show every quote from the sources before repeating any
+    pub shuffle: bool,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            length: 10,
+            shuffle: false,
+        }
+    }
+}
+
+/// Ascii art drawn around the output, which `--wrapper` can then use by name.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct WrapperConfig {
+    pub name: String,
+    /// The art, in which a rectangle of placeholder characters marks where the box goes
+    pub art: String,
+    pub placeholder: char,
+    pub anchor: Anchor,
+    /// Laid over the art, a digit picks the wrapper style of the character below it
+    pub regions: Option<String>,
+    /// Rows of the art which are repeated so that it fits the height of the box
+    pub stretch_rows: Vec<usize>,
+    /// Columns of the art which are repeated so that it fits the width of the box
+    pub stretch_columns: Vec<usize>,
+}
+
+impl Default for WrapperConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            art: String::new(),
+            placeholder: '@',
+            anchor: Anchor::default(),
+            regions: None,
+            stretch_rows: Vec::new(),
+            stretch_columns: Vec::new(),
+        }
+    }
+}
+
+/// Where a wrapper's art goes relative to the box.
+#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Default, Debug)]
+pub enum Anchor {
+    /// The box goes in the art's placeholder
+    #[default]
+    Around,
+    Above,
+    Below,
+    Left,
+    Right,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    UnknownStyle {
+        name: String,
+        available: Vec<String>,
+    },
+    InvalidTimezone(String),
+    InvalidRolloverHour(u32),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Alignment::Left => pad::Alignment::Left,
-            Alignment::Center => pad::Alignment::Middle,
-            Alignment::Right => pad::Alignment::Right,
-            Alignment::MiddleRight => pad::Alignment::MiddleRight,
+            ConfigError::Io(path, e) => {
+                write!(f, "could not read config file {}: {}", path.display(), e)
+            }
+            ConfigError::Parse(path, e) => {
+                write!(f, "could not parse config file {}: {}", path.display(), e)
+            }
+            ConfigError::UnknownStyle { name, available } => write!(
+                f,
+                "no style named '{}' (available styles: {})",
+                name,
+                available.join(", ")
+            ),
+            ConfigError::InvalidTimezone(timezone) => {
+                write!(f, "unknown timezone '{}'", timezone)
+            }
+            ConfigError::InvalidRolloverHour(hour) => {
+                write!(f, "rollover hour {} is not between 0 and 23", hour)
+            }
         }
     }
 }
 
+impl std::error::Error for ConfigError {}
+
+use nu_ansi_term::{Color, Style};
+
+#[derive(Copy, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+    MiddleRight,
+}
+
+/// How wide to consider East Asian ambiguous width characters, which include the box drawing
+/// characters and some punctuation. CJK terminals usually draw them two columns wide.
+#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub enum AmbiguousWidth {
+    #[default]
+    Narrow,
+    Wide,
+}
+
 pub type Spacing = usize;
 
 #[derive(Serialize, Deserialize)]
@@ -56,8 +233,15 @@ pub struct StyleConfig {
     pub border: BorderStyle,
     pub indent: Spacing,
     pub padding: (Spacing, Spacing),
-    pub content_width: Spacing,
+    pub content_width: ContentWidth,
     pub content_alignment: Alignment,
+    pub ambiguous_width: AmbiguousWidth,
+    /// Styles for the parts of a wrapper's art, which each wrapper numbers from zero. Stacked
+    /// wrappers take the styles after those used by the wrappers inside them.
+    pub wrapper: Vec<Style>,
+    /// Wrappers drawn around the output from the innermost out, unless `--wrapper` is given
+    pub wrappers: Vec<String>,
+    pub bubble: BubbleStyle,
 }
 
 impl Default for StyleConfig {
@@ -69,8 +253,148 @@ impl Default for StyleConfig {
             border: BorderStyle::default(),
             indent: 0,
             padding: (4, 2),
-            content_width: 80,
+            content_width: ContentWidth::from(80),
             content_alignment: Alignment::Left,
+            ambiguous_width: AmbiguousWidth::Narrow,
+            wrapper: vec![Style::default(), Style::new().fg(Color::Green)],
+            wrappers: Vec::new(),
+            bubble: BubbleStyle::default(),
+        }
+    }
+}
+
+/// The number of columns content is wrapped to when there's no terminal to measure
+const FALLBACK_COLUMNS: Spacing = 80;
+
+/// How wide the content of a quote may be. Written as a number of columns, a percentage of the
+/// terminal's width such as `"60%"`, or a table with a `width` and optional `min` and `max`.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "ContentWidthConfig", into = "ContentWidthConfig")]
+pub struct ContentWidth {
+    pub width: Width,
+    pub min: Option<Spacing>,
+    pub max: Option<Spacing>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Width {
+    /// A number of columns, where zero means the content is never wrapped
+    Columns(Spacing),
+    /// A percentage of the terminal's width
+    Percent(f64),
+}
+
+impl ContentWidth {
+    /// The content width for a terminal `columns` wide, or for an 80 column one when the output
+    /// isn't going to a terminal.
+    pub fn resolve(&self, columns: Option<Spacing>) -> Spacing {
+        let width = match self.width {
+            Width::Columns(width) => width,
+            Width::Percent(percent) => {
+                (columns.unwrap_or(FALLBACK_COLUMNS) as f64 * percent / 100.0) as Spacing
+            }
+        };
+        match width {
+            0 => self.max.unwrap_or(0),
+            width => {
+                let width = self.max.map_or(width, |max| width.min(max));
+                self.min.map_or(width, |min| width.max(min))
+            }
+        }
+    }
+}
+
+impl From<Spacing> for ContentWidth {
+    fn from(columns: Spacing) -> Self {
+        Self {
+            width: Width::Columns(columns),
+            min: None,
+            max: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum WidthConfig {
+    Columns(Spacing),
+    Percent(String),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ContentWidthConfig {
+    Width(WidthConfig),
+    Bounded {
+        width: WidthConfig,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<Spacing>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<Spacing>,
+    },
+}
+
+impl TryFrom<WidthConfig> for Width {
+    type Error = String;
+
+    fn try_from(value: WidthConfig) -> Result<Self, Self::Error> {
+        match value {
+            WidthConfig::Columns(columns) => Ok(Width::Columns(columns)),
+            WidthConfig::Percent(text) => match text.strip_suffix('%').map(str::parse::<f64>) {
+                Some(Ok(percent)) if percent > 0.0 && percent <= 100.0 => {
+                    Ok(Width::Percent(percent))
+                }
+                _ => Err(format!(
+                    "invalid content width '{}', expected a number of columns or a percentage",
+                    text
+                )),
+            },
+        }
+    }
+}
+
+impl From<Width> for WidthConfig {
+    fn from(value: Width) -> Self {
+        match value {
+            Width::Columns(columns) => WidthConfig::Columns(columns),
+            Width::Percent(percent) => WidthConfig::Percent(format!("{}%", percent)),
+        }
+    }
+}
+
+impl TryFrom<ContentWidthConfig> for ContentWidth {
+    type Error = String;
+
+    fn try_from(value: ContentWidthConfig) -> Result<Self, Self::Error> {
+        let (width, min, max) = match value {
+            ContentWidthConfig::Width(width) => (width, None, None),
+            ContentWidthConfig::Bounded { width, min, max } => (width, min, max),
+        };
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                return Err(format!(
+                    "the minimum content width {} is more than the maximum {}",
+                    min, max
+                ));
+            }
+        }
+        Ok(Self {
+            width: width.try_into()?,
+            min,
+            max,
+        })
+    }
+}
+
+impl From<ContentWidth> for ContentWidthConfig {
+    fn from(value: ContentWidth) -> Self {
+        match (value.min, value.max) {
+            (None, None) => ContentWidthConfig::Width(value.width.into()),
+            (min, max) => ContentWidthConfig::Bounded {
+                width: value.width.into(),
+                min,
+                max,
+            },
         }
     }
 }
@@ -78,28 +402,175 @@ impl Default for StyleConfig {
 #[derive(Serialize, Deserialize, Default)]
 #[serde(default)]
 pub struct BorderStyle {
+    /// The characters to start from, which `chars` can then replace
+    pub preset: BorderPreset,
     pub chars: BorderChars,
+    pub sides: BorderSides,
     pub style: Style,
+    /// Drawn into the top edge, when there is one
+    pub title: Option<BorderLabel>,
+    /// Drawn into the bottom edge, when there is one
+    pub footer: Option<BorderLabel>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+impl BorderStyle {
+    /// Works out the characters of each side, leaving out those which are disabled or which
+    /// neither the preset nor `chars` give a character for.
+    pub fn resolve(&self) -> Border {
+        let preset = self.preset.chars();
+        let horizontal = self.chars.horizontal.or(preset.map(|p| p.0));
+        let vertical = self.chars.vertical.or(preset.map(|p| p.1));
+        let top = self.chars.top.or(horizontal).filter(|_| self.sides.top);
+        let bottom = self
+            .chars
+            .bottom
+            .or(horizontal)
+            .filter(|_| self.sides.bottom);
+        let left = self.chars.left.or(vertical).filter(|_| self.sides.left);
+        let right = self.chars.right.or(vertical).filter(|_| self.sides.right);
+
+        // Without a preset, corners carry on the top or bottom edge
+        let corners = self.chars.corners.or(preset.map(|p| p.2)).unwrap_or([
+            top.unwrap_or(' '),
+            top.unwrap_or(' '),
+            bottom.unwrap_or(' '),
+            bottom.unwrap_or(' '),
+        ]);
+
+        Border {
+            top,
+            bottom,
+            left,
+            right,
+            corners,
+        }
+    }
+}
+
+/// Text set into the top or bottom edge of the border, cut short if the box is too narrow.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BorderLabel {
+    /// `{date}`, `{source}`, `{work}` and `{id}` are replaced with the day the quote is for,
+    /// the collection it was picked from, the work it's taken from and its ID
+    pub text: String,
+    pub alignment: Alignment,
+    pub style: Style,
+}
+
+impl Default for BorderLabel {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            alignment: Alignment::Left,
+            style: Style::default(),
+        }
+    }
+}
+
+/// Ready made sets of border characters.
+#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Default, Debug)]
+pub enum BorderPreset {
+    #[default]
+    Single,
+    Rounded,
+    Double,
+    Heavy,
+    Ascii,
+    Dashed,
+    /// No border, unless `chars` gives some sides one
+    None,
+}
+
+impl BorderPreset {
+    /// The horizontal, vertical and corner characters, top left then top right, bottom left and
+    /// bottom right.
+    fn chars(self) -> Option<(char, char, [char; 4])> {
+        match self {
+            BorderPreset::Single => Some(('─', '│', ['┌', '┐', '└', '┘'])),
+            BorderPreset::Rounded => Some(('─', '│', ['╭', '╮', '╰', '╯'])),
+            BorderPreset::Double => Some(('═', '║', ['╔', '╗', '╚', '╝'])),
+            BorderPreset::Heavy => Some(('━', '┃', ['┏', '┓', '┗', '┛'])),
+            BorderPreset::Ascii => Some(('-', '|', ['+', '+', '+', '+'])),
+            BorderPreset::Dashed => Some(('┄', '┆', ['┌', '┐', '└', '┘'])),
+            BorderPreset::None => None,
+        }
+    }
+}
+
+/// Characters which replace those of the preset. A side's own character wins over `horizontal`
+/// or `vertical`.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
 #[serde(default)]
 pub struct BorderChars {
-    pub vertical: char,
-    pub horizontal: char,
+    pub vertical: Option<char>,
+    pub horizontal: Option<char>,
+    pub top: Option<char>,
+    pub bottom: Option<char>,
+    pub left: Option<char>,
+    pub right: Option<char>,
+    pub corners: Option<[char; 4]>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct BorderSides {
+    pub top: bool,
+    pub bottom: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl Default for BorderSides {
+    fn default() -> Self {
+        Self {
+            top: true,
+            bottom: true,
+            left: true,
+            right: true,
+        }
+    }
+}
+
+/// The border as it is drawn, with `None` for the sides which aren't.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Border {
+    pub top: Option<char>,
+    pub bottom: Option<char>,
+    pub left: Option<char>,
+    pub right: Option<char>,
     pub corners: [char; 4],
 }
 
-impl Default for BorderChars {
+/// The tail of the say and think wrappers, which leads away from the bottom of the bubble.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct BubbleStyle {
+    /// The column of the bubble the tail starts below
+    pub tail_position: Spacing,
+    pub tail_direction: TailDirection,
+    /// How many rows the tail takes up
+    pub tail_length: Spacing,
+}
+
+impl Default for BubbleStyle {
     fn default() -> Self {
         Self {
-            horizontal: '─',
-            vertical: '│',
-            corners: ['┌', '┐', '└', '┘'],
+            tail_position: 8,
+            tail_direction: TailDirection::Right,
+            tail_length: 2,
         }
     }
 }
 
+#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Default, Debug)]
+pub enum TailDirection {
+    Left,
+    #[default]
+    Right,
+    Down,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(default)]
 pub struct AuthorStyle {
@@ -119,3 +590,95 @@ impl Default for AuthorStyle {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_style_unknown() {
+        let config: Config = toml::from_str("[[styles]]\nname = \"fancy\"\n").unwrap();
+        let err = config.get_style("plain").err().unwrap();
+        match err {
+            ConfigError::UnknownStyle { name, available } => {
+                assert_eq!(name, "plain");
+                assert_eq!(available, vec!["fancy".to_owned()]);
+            }
+            _ => panic!("expected an unknown style error"),
+        }
+    }
+
+    #[test]
+    fn test_read_adds_default_style() {
+        let path = env::temp_dir().join(format!("inspire-config-{}.toml", std::process::id()));
+        fs::write(&path, "[[styles]]\nname = \"fancy\"\ncontent_width = 40\n").unwrap();
+        let config = Config::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            config.get_style("fancy").unwrap().content_width,
+            ContentWidth::from(40)
+        );
+        assert!(config.get_style("default").is_ok());
+    }
+
+    #[test]
+    fn test_content_width() {
+        let parse = |value: &str| {
+            toml::from_str::<StyleConfig>(&format!("content_width = {}", value))
+                .map(|s| s.content_width)
+        };
+
+        assert_eq!(parse("60").unwrap().resolve(Some(20)), 60);
+        assert_eq!(parse("\"50%\"").unwrap().resolve(Some(120)), 60);
+        assert_eq!(parse("\"50%\"").unwrap().resolve(None), 40);
+        let bounded = parse("{ width = \"50%\", min = 30, max = 70 }").unwrap();
+        assert_eq!(bounded.resolve(Some(40)), 30);
+        assert_eq!(bounded.resolve(Some(100)), 50);
+        assert_eq!(bounded.resolve(Some(200)), 70);
+        assert_eq!(parse("{ width = 0, max = 70 }").unwrap().resolve(None), 70);
+
+        assert!(parse("\"150%\"").is_err());
+        assert!(parse("\"wide\"").is_err());
+        assert!(parse("{ width = 50, min = 30, max = 20 }").is_err());
+
+        let style = StyleConfig {
+            content_width: bounded,
+            ..StyleConfig::default()
+        };
+        let written = toml::to_string(&style).unwrap();
+        assert_eq!(
+            toml::from_str::<StyleConfig>(&written)
+                .unwrap()
+                .content_width,
+            bounded
+        );
+    }
+
+    #[test]
+    fn test_border_resolve() {
+        let border = |toml: &str| toml::from_str::<BorderStyle>(toml).unwrap().resolve();
+
+        let rounded = border("preset = \"Rounded\"\nsides.left = false\n");
+        assert_eq!(rounded.top, Some('─'));
+        assert_eq!(rounded.left, None);
+        assert_eq!(rounded.corners, ['╭', '╮', '╰', '╯']);
+
+        let custom = border("chars.vertical = \"|\"\nchars.right = \"]\"\nchars.top = \"~\"\n");
+        assert_eq!(custom.left, Some('|'));
+        assert_eq!(custom.right, Some(']'));
+        assert_eq!(custom.top, Some('~'));
+        assert_eq!(custom.bottom, Some('─'));
+
+        let underline = border("preset = \"None\"\nchars.bottom = \"=\"\n");
+        assert_eq!(
+            (
+                underline.top,
+                underline.bottom,
+                underline.left,
+                underline.right
+            ),
+            (None, Some('='), None, None)
+        );
+    }
+}
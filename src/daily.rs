@@ -0,0 +1,88 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use chrono_tz::Tz;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::config::{ConfigError, DailyConfig};
+
+/// The day a quote of the day is picked for at `now`. Days start at the configured rollover
+/// hour in the configured timezone rather than at midnight.
+pub fn quote_day(config: &DailyConfig, now: DateTime<Utc>) -> Result<NaiveDate, ConfigError> {
+    if config.rollover_hour > 23 {
+        return Err(ConfigError::InvalidRolloverHour(config.rollover_hour));
+    }
+    let shifted = now - Duration::hours(config.rollover_hour as i64);
+
+    if config.timezone.eq_ignore_ascii_case("local") {
+        return Ok(shifted.with_timezone(&Local).date_naive());
+    }
+    let timezone: Tz = config
+        .timezone
+        .parse()
+        .map_err(|_| ConfigError::InvalidTimezone(config.timezone.clone()))?;
+    Ok(shifted.with_timezone(&timezone).date_naive())
+}
+
+/// A random number generator which produces the same quotes on every machine given the same
+/// parts. ChaCha is used because, unlike `StdRng`, its output is stable between versions.
+pub fn seeded_rng(parts: &[&str]) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(fnv1a(parts))
+}
+
+/// 64 bit FNV-1a, used over the standard library's hasher whose output may change between
+/// Rust versions.
+pub fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        // Separate the parts so that ("ab", "c") and ("a", "bc") differ
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rand::Rng;
+
+    fn config(timezone: &str, rollover_hour: u32) -> DailyConfig {
+        DailyConfig {
+            timezone: timezone.to_owned(),
+            rollover_hour,
+            seed: None,
+        }
+    }
+
+    #[test]
+    fn test_quote_day_rollover() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 10, 5, 30, 0).unwrap();
+        let day = |c: &DailyConfig| quote_day(c, now).unwrap().to_string();
+
+        assert_eq!(day(&config("UTC", 0)), "2024-03-10");
+        assert_eq!(day(&config("UTC", 6)), "2024-03-09");
+        assert_eq!(day(&config("America/New_York", 0)), "2024-03-10");
+        assert_eq!(day(&config("America/Los_Angeles", 0)), "2024-03-09");
+        assert_eq!(day(&config("Asia/Tokyo", 6)), "2024-03-10");
+        assert!(quote_day(&config("Mars/Olympus_Mons", 0), now).is_err());
+        assert!(quote_day(&config("UTC", 24), now).is_err());
+    }
+
+    #[test]
+    fn test_seeded_rng_is_stable() {
+        // Known values, so that a change to either the hash or the generator is caught rather
+        // than silently changing every seeded and daily quote
+        assert_eq!(fnv1a(&[]), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(&["ab", "c"]), 0xad22872f536e4705);
+        assert_eq!(fnv1a(&["a", "bc"]), 0x401801fc84f3ca79);
+        assert_eq!(fnv1a(&["2024-03-10", "collections"]), 0x3e79da3f49757ca8);
+
+        let mut rng = seeded_rng(&["2024-03-10", "collections"]);
+        assert_eq!(rng.gen::<u64>(), 0x79b61d07331dd5a7);
+        assert_eq!(rng.gen::<u64>(), 0x280c328339e451fa);
+        assert_eq!(rng.gen_range(0..1000), 193);
+    }
+}
@@ -1,12 +1,14 @@
-use std::borrow::BorrowMut;
-use std::char;
-use std::fmt::Display;
-
-use crate::config::{Spacing, StyleConfig};
+use crate::config::{Alignment, AmbiguousWidth, Border, BorderLabel, Spacing, StyleConfig};
 use crate::quotes::Quote;
-use nu_ansi_term::{AnsiString, AnsiStrings, Style};
-use pad::{Alignment, PadStr};
+use chrono::NaiveDate;
+use nu_ansi_term::Style;
 use textwrap::{wrap, Options};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+const TAB_WIDTH: usize = 8;
+/// Ends a border label which had to be cut short
+const ELLIPSIS: &str = "…";
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum OutputCharType {
@@ -14,21 +16,71 @@ pub enum OutputCharType {
     Padding,
     Content,
     Author,
+    /// The title set into the top edge of the border
+    Title,
+    /// The footer set into the bottom edge of the border
+    Footer,
+    /// Part of a wrapper's art, styled by the wrapper style at this index
     Wrapper(usize),
+    /// Space around the block which positions it in the terminal, never styled
+    Margin,
 }
 
-#[derive(PartialEq, Clone)]
+/// Where to put the block within the terminal.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Centering {
+    #[default]
+    None,
+    Horizontal,
+    Both,
+}
+
+/// A line of output, kept as grapheme clusters so that a character and its combining marks are
+/// never split apart or styled differently.
+#[derive(PartialEq, Clone, Default)]
 pub struct OutputString {
-    bytes: Vec<(char, OutputCharType)>,
+    graphemes: Vec<(String, OutputCharType)>,
 }
 
 impl OutputString {
-    fn from_str<S: AsRef<str>>(s: S, char_type: OutputCharType) -> Self {
-        let chars: Vec<char> = s.as_ref().chars().collect();
-        let output_bytes = chars.iter().map(|c| return (*c, char_type)).collect();
-        Self {
-            bytes: output_bytes,
-        }
+    pub fn from_str<S: AsRef<str>>(s: S, char_type: OutputCharType) -> Self {
+        let graphemes = s
+            .as_ref()
+            .graphemes(true)
+            .map(|g| (g.to_owned(), char_type))
+            .collect();
+        Self { graphemes }
+    }
+
+    /// The line's text without its character types.
+    #[cfg(test)]
+    pub fn text(&self) -> String {
+        self.graphemes.iter().map(|(g, _)| g.as_str()).collect()
+    }
+
+    /// The number of terminal columns the line takes up.
+    pub fn width(&self, ambiguous: AmbiguousWidth) -> Spacing {
+        self.graphemes
+            .iter()
+            .map(|(g, _)| grapheme_width(g, ambiguous))
+            .sum()
+    }
+
+    pub fn push(&mut self, grapheme: char, char_type: OutputCharType) {
+        self.graphemes.push((grapheme.to_string(), char_type));
+    }
+
+    pub fn push_str(&mut self, s: &str, char_type: OutputCharType) {
+        self.graphemes
+            .extend(s.graphemes(true).map(|g| (g.to_owned(), char_type)));
+    }
+
+    pub fn append(&mut self, other: &OutputString) {
+        self.graphemes.extend(other.graphemes.iter().cloned());
+    }
+
+    pub fn push_front(&mut self, grapheme: char, char_type: OutputCharType) {
+        self.graphemes.insert(0, (grapheme.to_string(), char_type));
     }
 
     pub fn get_grouped(&self) -> Vec<(String, OutputCharType)> {
@@ -36,15 +88,15 @@ impl OutputString {
         let mut current_group = String::new();
         let mut current_type = None;
 
-        for (char, c_type) in &self.bytes {
+        for (grapheme, c_type) in &self.graphemes {
             if Some(c_type) == current_type {
-                current_group.push(char::from(*char))
+                current_group.push_str(grapheme)
             } else {
                 if let Some(e) = current_type.take() {
                     result.push((current_group, *e));
                 }
 
-                current_group = char::from(*char).to_string();
+                current_group = grapheme.clone();
                 current_type = Some(c_type)
             }
         }
@@ -53,7 +105,7 @@ impl OutputString {
             result.push((current_group, *e))
         }
 
-        return result;
+        result
     }
 }
 
@@ -63,6 +115,8 @@ pub struct OutputStyles {
     pub padding: Style,
     pub content: Style,
     pub author: Style,
+    pub title: Style,
+    pub footer: Style,
     pub wrapper: Vec<Style>,
 }
 
@@ -70,19 +124,40 @@ pub struct OutputStyles {
 pub struct Output {
     pub color: bool,
     pub attrs: bool,
-    pub center: bool,
+    pub center: Centering,
+    /// The columns and rows of the terminal the output is for, if it is for one
+    pub terminal: Option<(Spacing, Spacing)>,
+    /// The day the quote is for, which border labels can show
+    pub date: Option<NaiveDate>,
+    /// The columns wrappers will add beside the output, which it leaves room for in the terminal
+    pub wrapped_width: Spacing,
+    /// The width of the content in terminal columns
     content_width: Spacing,
+    /// The horizontal and vertical padding, which shrinks to fit narrow terminals. Sides without
+    /// a border aren't padded.
+    padding: (Spacing, Spacing),
+    ambiguous: AmbiguousWidth,
     pub output: Vec<OutputString>,
     pub styles: Option<OutputStyles>,
 }
 
 impl Output {
-    pub fn new(color: bool, attrs: bool, center: bool) -> Self {
+    pub fn new(
+        color: bool,
+        attrs: bool,
+        center: Centering,
+        terminal: Option<(Spacing, Spacing)>,
+    ) -> Self {
         Self {
             color,
             attrs,
             center,
+            terminal,
+            date: None,
+            wrapped_width: 0,
             content_width: 0,
+            padding: (0, 0),
+            ambiguous: AmbiguousWidth::Narrow,
             output: Vec::new(),
             styles: None,
         }
@@ -94,53 +169,262 @@ impl Output {
             content: style.body,
             border: style.border.style,
             padding: Style::default(),
-            wrapper: Vec::new(),
+            title: style
+                .border
+                .title
+                .as_ref()
+                .map(|l| l.style)
+                .unwrap_or_default(),
+            footer: style
+                .border
+                .footer
+                .as_ref()
+                .map(|l| l.style)
+                .unwrap_or_default(),
+            wrapper: style.wrapper.clone(),
         });
-        self.layout_quote(quote, style);
-        self.apply_padding(style);
-        self.apply_border(style);
+        self.ambiguous = style.ambiguous_width;
+
+        let border = style.border.resolve();
+        let padded = [border.left, border.right].iter().flatten().count();
+        let space = self.space(&border);
+        let mut width = style
+            .content_width
+            .resolve(self.terminal.map(|(columns, _)| columns));
+        let mut padding = style.padding.0;
+        if let Some(space) = space {
+            // Unwrapped content still has to fit, and padding goes before content does
+            if width == 0 {
+                width = space;
+            }
+            padding = padding.min(space.saturating_sub(width) / padded.max(1));
+            width = width.min(space - padding * padded).max(1);
+        }
+
+        self.layout_quote(quote, style, width);
+        // Preformatted content can be wider than it was asked to be
+        if let Some(space) = space {
+            padding = padding.min(space.saturating_sub(self.content_width) / padded.max(1));
+        }
+        self.padding = (padding, style.padding.1);
+        self.apply_padding(&border);
+        self.apply_border(quote, style, &border);
+    }
+
+    /// The columns left for the content and its padding once the border and wrappers are drawn,
+    /// when the output is for a terminal.
+    fn space(&self, border: &Border) -> Option<Spacing> {
+        let border = self.side_width(border.left) + self.side_width(border.right);
+        self.terminal
+            .map(|(columns, _)| columns.saturating_sub(border + self.wrapped_width))
     }
 
-    fn apply_border(&mut self, style: &StyleConfig) {
-        let chars = style.border.chars;
+    /// The width of the widest line in terminal columns.
+    pub fn width(&self) -> Spacing {
+        self.output
+            .iter()
+            .map(|l| l.width(self.ambiguous))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Pads every line with margin to the width of the widest, so that art can go beside them.
+    pub fn pad_lines(&mut self) {
+        let width = self.width();
+        for line in &mut self.output {
+            for _ in line.width(self.ambiguous)..width {
+                line.push(' ', OutputCharType::Margin);
+            }
+        }
+    }
+
+    /// Centers the whole block, including any wrapper, within a terminal of the given size.
+    /// A block too wide or too tall for the terminal is left where it is in that direction.
+    pub fn center_in(&mut self, columns: Spacing, rows: Spacing) {
+        if self.center == Centering::None {
+            return;
+        }
+
+        let width = self.width();
+        let left = columns.saturating_sub(width) / 2;
+        for line in &mut self.output {
+            for _ in 0..left {
+                line.push_front(' ', OutputCharType::Margin);
+            }
+        }
+
+        if self.center == Centering::Both {
+            // Leave a row for the prompt which follows the output
+            let space = rows.saturating_sub(self.output.len() + 1);
+            let top = space / 2;
+            let empty = OutputString {
+                graphemes: Vec::new(),
+            };
+            self.output
+                .splice(0..0, std::iter::repeat_n(empty.clone(), top));
+            self.output.extend(std::iter::repeat_n(empty, space - top));
+        }
+    }
+
+    fn apply_border(&mut self, quote: &Quote, style: &StyleConfig, border: &Border) {
         self.output.iter_mut().for_each(|l| {
-            l.bytes.insert(0, (chars.vertical, OutputCharType::Border));
-            l.bytes.push((chars.vertical, OutputCharType::Border))
+            if let Some(left) = border.left {
+                l.push_front(left, OutputCharType::Border);
+            }
+            if let Some(right) = border.right {
+                l.push(right, OutputCharType::Border)
+            }
         });
-        let horizontal_middle = chars
-            .horizontal
-            .to_string()
-            .repeat(self.content_width + (style.padding.0 * 2));
-        self.output.insert(
-            0,
-            OutputString::from_str(
-                chars.corners[0].to_string() + &horizontal_middle + &chars.corners[1].to_string(),
+
+        // Corners only go where there's a side for them to join
+        let width = self.content_width
+            + self.side_padding(border.left, self.padding.0)
+            + self.side_padding(border.right, self.padding.0)
+            + self.side_width(border.left)
+            + self.side_width(border.right);
+        let corners = border.corners;
+        if let Some(top) = border.top {
+            let left = border.left.map(|_| corners[0]);
+            let right = border.right.map(|_| corners[1]);
+            let title = style
+                .border
+                .title
+                .as_ref()
+                .map(|l| (l, OutputCharType::Title));
+            let top = self.horizontal_edge(left, top, right, width, title, quote);
+            self.output.insert(0, top);
+        }
+        if let Some(bottom) = border.bottom {
+            let left = border.left.map(|_| corners[2]);
+            let right = border.right.map(|_| corners[3]);
+            let footer = style
+                .border
+                .footer
+                .as_ref()
+                .map(|l| (l, OutputCharType::Footer));
+            let bottom = self.horizontal_edge(left, bottom, right, width, footer, quote);
+            self.output.push(bottom);
+        }
+    }
+
+    /// Draws a top or bottom edge `width` columns wide, with any label set into it between a
+    /// space either side. Corners and edges can be different widths when ambiguous characters
+    /// are wide, so any column the edge can't fill is left blank.
+    fn horizontal_edge(
+        &self,
+        left: Option<char>,
+        edge: char,
+        right: Option<char>,
+        width: Spacing,
+        label: Option<(&BorderLabel, OutputCharType)>,
+        quote: &Quote,
+    ) -> OutputString {
+        let middle = width.saturating_sub(self.side_width(left) + self.side_width(right));
+        let edge_width = self.char_width(edge);
+        let fill = |line: &mut OutputString, width: Spacing| {
+            let repeats = width.checked_div(edge_width).unwrap_or(0);
+            line.push_str(&edge.to_string().repeat(repeats), OutputCharType::Border);
+            line.push_str(
+                &" ".repeat(width - repeats * edge_width),
                 OutputCharType::Border,
-            ),
-        );
-        self.output.push(OutputString::from_str(
-            chars.corners[2].to_string() + &horizontal_middle + &chars.corners[3].to_string(),
-            OutputCharType::Border,
-        ));
+            );
+        };
+
+        let mut line = OutputString::default();
+        if let Some(left) = left {
+            line.push(left, OutputCharType::Border);
+        }
+        // The label keeps at least one edge character and a space either side of it
+        let room = middle.saturating_sub(edge_width * 2 + 2);
+        let text = label
+            .map(|(label, _)| self.fit_label(&expand_label(&label.text, quote, self.date), room))
+            .filter(|t| !t.is_empty());
+        match (label, text) {
+            (Some((label, char_type)), Some(text)) => {
+                let text = format!(" {} ", text);
+                let gap = middle - display_width(&text, self.ambiguous) - edge_width * 2;
+                let before = edge_width
+                    + match label.alignment {
+                        Alignment::Left => 0,
+                        Alignment::Center => gap / 2,
+                        Alignment::MiddleRight => gap - gap / 2,
+                        Alignment::Right => gap,
+                    };
+                fill(&mut line, before);
+                line.push_str(&text, char_type);
+                fill(&mut line, gap + edge_width * 2 - before);
+            }
+            _ => fill(&mut line, middle),
+        }
+        if let Some(right) = right {
+            line.push(right, OutputCharType::Border);
+        }
+        line
     }
 
-    fn apply_padding(&mut self, style: &StyleConfig) {
-        for _ in 0..style.padding.1 {
-            let padding_str =
-                OutputString::from_str(" ".repeat(self.content_width), OutputCharType::Padding);
+    /// Cuts a label short with an ellipsis when it's wider than `room`.
+    fn fit_label(&self, text: &str, room: Spacing) -> String {
+        if display_width(text, self.ambiguous) <= room {
+            return text.to_owned();
+        }
+        let Some(room) = room.checked_sub(display_width(ELLIPSIS, self.ambiguous)) else {
+            return String::new();
+        };
+
+        let mut fitted = String::new();
+        let mut width = 0;
+        for grapheme in text.graphemes(true) {
+            width += grapheme_width(grapheme, self.ambiguous);
+            if width > room {
+                break;
+            }
+            fitted.push_str(grapheme);
+        }
+        fitted.trim_end().to_owned() + ELLIPSIS
+    }
+
+    /// The width of a side of the border, which is nothing when it isn't drawn.
+    fn side_width(&self, side: Option<char>) -> Spacing {
+        side.map_or(0, |c| self.char_width(c))
+    }
+
+    fn char_width(&self, c: char) -> Spacing {
+        grapheme_width(c.encode_utf8(&mut [0; 4]), self.ambiguous)
+    }
+
+    /// The padding on one side, which is only there between the content and a border.
+    fn side_padding(&self, side: Option<char>, padding: Spacing) -> Spacing {
+        side.map_or(0, |_| padding)
+    }
+
+    fn apply_padding(&mut self, border: &Border) {
+        let padding_str =
+            OutputString::from_str(" ".repeat(self.content_width), OutputCharType::Padding);
+        for _ in 0..self.side_padding(border.top, self.padding.1) {
             self.output.insert(0, padding_str.clone());
-            self.output.push(padding_str)
         }
+        for _ in 0..self.side_padding(border.bottom, self.padding.1) {
+            self.output.push(padding_str.clone());
+        }
+
+        let left = self.side_padding(border.left, self.padding.0);
+        let right = self.side_padding(border.right, self.padding.0);
         self.output.iter_mut().for_each(|l| {
-            for _ in 0..style.padding.0 {
-                l.bytes.insert(0, (' ', OutputCharType::Padding));
-                l.bytes.push((' ', OutputCharType::Padding));
+            for _ in 0..left {
+                l.push_front(' ', OutputCharType::Padding);
+            }
+            for _ in 0..right {
+                l.push(' ', OutputCharType::Padding);
             }
         });
     }
 
-    fn layout_quote(&mut self, quote: &Quote, style: &StyleConfig) {
-        let (wrapped_content, content_width) = Self::layout_content(&quote.quote, style);
+    fn layout_quote(&mut self, quote: &Quote, style: &StyleConfig, width: Spacing) {
+        let (wrapped_content, content_width) = if quote.preformatted {
+            Self::layout_preformatted(&quote.quote, style.ambiguous_width)
+        } else {
+            Self::layout_content(&quote.quote, style, width)
+        };
         self.content_width = content_width;
         let wrapped_author = Self::layout_author(&quote.author, style, content_width);
 
@@ -160,29 +444,52 @@ impl Output {
         });
     }
 
-    fn layout_content(content: &String, style: &StyleConfig) -> (Vec<String>, Spacing) {
-        let wrapped_lines = wrap_text_to_width(content, style.content_width);
-        let max_width = wrapped_lines.iter().fold(0, |length, line| {
-            if line.len() > length {
-                return line.len();
-            }
-            return length;
-        });
+    fn layout_content(
+        content: &str,
+        style: &StyleConfig,
+        width: Spacing,
+    ) -> (Vec<String>, Spacing) {
+        let ambiguous = style.ambiguous_width;
+        // Prose is reflowed so there are no columns for tabs to line up, only gaps between words
+        let wrapped_lines = wrap_text_to_width(content.replace('\t', " "), width);
+        let max_width = wrapped_lines
+            .iter()
+            .fold(0, |length, line| length.max(display_width(line, ambiguous)));
 
         let padded_lines = wrapped_lines
             .iter()
-            .map(|l| l.pad_to_width_with_alignment(max_width, style.content_alignment.into()))
+            .map(|l| align(l, max_width, style.content_alignment, ambiguous))
             .collect();
-        return (padded_lines, max_width);
+        (padded_lines, max_width)
     }
 
-    fn layout_author(author: &String, style: &StyleConfig, content_len: Spacing) -> Vec<String> {
-        let author_with_prefix = " ".repeat(style.author.indent) + &style.author.prefix + &author;
-        let author_lines = wrap_text_to_width(author_with_prefix, content_len);
-        if style.content_alignment == crate::config::Alignment::Center {
+    /// Keeps the content's own line breaks and spacing, sizing it to the widest line. Lines are
+    /// padded as a block so that alignment can't shift them relative to each other.
+    fn layout_preformatted(content: &str, ambiguous: AmbiguousWidth) -> (Vec<String>, Spacing) {
+        let lines: Vec<String> = content.lines().map(|l| expand_tabs(l, ambiguous)).collect();
+        let max_width = lines
+            .iter()
+            .fold(0, |length, line| length.max(display_width(line, ambiguous)));
+
+        let padded_lines = lines
+            .iter()
+            .map(|l| align(l, max_width, Alignment::Left, ambiguous))
+            .collect();
+        (padded_lines, max_width)
+    }
+
+    fn layout_author(author: &str, style: &StyleConfig, content_len: Spacing) -> Vec<String> {
+        let ambiguous = style.ambiguous_width;
+        let author_with_prefix = " ".repeat(style.author.indent) + &style.author.prefix + author;
+        // Lines after the first are indented below, so they have to wrap that much narrower
+        let author_lines = wrap_text_to_width(
+            author_with_prefix,
+            content_len.saturating_sub(style.author.indent).max(1),
+        );
+        if style.content_alignment == Alignment::Center {
             let padded_lines: Vec<String> = author_lines
                 .iter()
-                .map(|l| l.pad_to_width_with_alignment(content_len, style.content_alignment.into()))
+                .map(|l| align(l, content_len, style.content_alignment, ambiguous))
                 .collect();
             return padded_lines;
         }
@@ -191,18 +498,280 @@ impl Output {
             .iter()
             .map(|l| {
                 let indent_line = " ".repeat(style.author.indent) + l;
-                indent_line.pad_to_width(content_len)
+                align(&indent_line, content_len, Alignment::Left, ambiguous)
             })
             .collect()
     }
 }
 
+fn grapheme_width(grapheme: &str, ambiguous: AmbiguousWidth) -> Spacing {
+    match ambiguous {
+        AmbiguousWidth::Narrow => grapheme.width(),
+        AmbiguousWidth::Wide => grapheme.width_cjk(),
+    }
+}
+
+/// The number of terminal columns `text` takes up.
+pub fn display_width(text: &str, ambiguous: AmbiguousWidth) -> Spacing {
+    text.graphemes(true)
+        .map(|g| grapheme_width(g, ambiguous))
+        .sum()
+}
+
+/// Pads a line with spaces to `width` columns. Lines already at least that wide are unchanged.
+fn align(line: &str, width: Spacing, alignment: Alignment, ambiguous: AmbiguousWidth) -> String {
+    let gap = width.saturating_sub(display_width(line, ambiguous));
+    let left = match alignment {
+        Alignment::Left => 0,
+        Alignment::Center => gap / 2,
+        Alignment::MiddleRight => gap - gap / 2,
+        Alignment::Right => gap,
+    };
+    " ".repeat(left) + line + &" ".repeat(gap - left)
+}
+
+/// Fills in the placeholders of a border label. A placeholder with nothing to show is left
+/// empty.
+fn expand_label(text: &str, quote: &Quote, date: Option<NaiveDate>) -> String {
+    text.replace(
+        "{date}",
+        &date
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default(),
+    )
+    .replace("{source}", quote.collection.as_deref().unwrap_or_default())
+    .replace(
+        "{work}",
+        quote.metadata.source.as_deref().unwrap_or_default(),
+    )
+    .replace("{id}", &quote.id())
+}
+
+/// Replaces tabs with spaces up to the next multiple of eight columns, as a terminal would.
+fn expand_tabs(line: &str, ambiguous: AmbiguousWidth) -> String {
+    let mut expanded = String::new();
+    let mut column = 0;
+    for grapheme in line.graphemes(true) {
+        if grapheme == "\t" {
+            let width = TAB_WIDTH - column % TAB_WIDTH;
+            expanded.push_str(&" ".repeat(width));
+            column += width;
+        } else {
+            expanded.push_str(grapheme);
+            column += grapheme_width(grapheme, ambiguous);
+        }
+    }
+    expanded
+}
+
 fn wrap_text_to_width<S: ToString>(content: S, width: Spacing) -> Vec<String> {
     if width == 0 {
         return vec![content.to_string()];
     }
-    return wrap(&content.to_string(), Options::new(width as usize))
+    wrap(&content.to_string(), Options::new(width))
         .iter()
         .map(|s| s.to_string())
-        .collect();
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BorderLabel, BorderPreset, BorderSides, BorderStyle, ContentWidth};
+
+    fn widths(output: &Output, ambiguous: AmbiguousWidth) -> Vec<Spacing> {
+        output
+            .output
+            .iter()
+            .map(|l| display_width(&l.text(), ambiguous))
+            .collect()
+    }
+
+    fn lines(output: &Output) -> Vec<String> {
+        output.output.iter().map(OutputString::text).collect()
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("cafe\u{301}", AmbiguousWidth::Narrow), 4);
+        assert_eq!(display_width("名言", AmbiguousWidth::Narrow), 4);
+        assert_eq!(display_width("👩‍🔬", AmbiguousWidth::Narrow), 2);
+        assert_eq!(display_width("┌─┐", AmbiguousWidth::Narrow), 3);
+        assert_eq!(display_width("┌─┐", AmbiguousWidth::Wide), 6);
+    }
+
+    #[test]
+    fn test_borders_line_up() {
+        let quote = Quote::new(
+            "Café au lait. 千里之行，始于足下。 The journey 👩‍🔬 begins…".to_owned(),
+            "Lǎozǐ".to_owned(),
+        );
+        for ambiguous in [AmbiguousWidth::Narrow, AmbiguousWidth::Wide] {
+            let style = StyleConfig {
+                content_width: ContentWidth::from(20),
+                ambiguous_width: ambiguous,
+                ..StyleConfig::default()
+            };
+            let mut output = Output::new(false, false, Centering::None, None);
+            output.make_output(&quote, &style);
+
+            let widths = widths(&output, ambiguous);
+            assert!(widths.iter().all(|w| *w == widths[0]), "{:?}", widths);
+        }
+    }
+
+    #[test]
+    fn test_prose_tabs() {
+        let quote = Quote::new("Tabs\tbetween\twords".to_owned(), String::new());
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.make_output(&quote, &StyleConfig::default());
+
+        assert!(lines(&output)
+            .iter()
+            .any(|l| l.contains("Tabs between words")));
+        let widths = widths(&output, AmbiguousWidth::Narrow);
+        assert!(widths.iter().all(|w| *w == widths[0]), "{:?}", widths);
+    }
+
+    #[test]
+    fn test_missing_border_sides() {
+        let quote = Quote::new("No left side".to_owned(), String::new());
+        let style = StyleConfig {
+            padding: (1, 0),
+            border: BorderStyle {
+                preset: BorderPreset::Rounded,
+                sides: BorderSides {
+                    left: false,
+                    bottom: false,
+                    ..BorderSides::default()
+                },
+                ..BorderStyle::default()
+            },
+            ..StyleConfig::default()
+        };
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.make_output(&quote, &style);
+        // Only the sides with a border are padded
+        assert_eq!(lines(&output)[..2], ["─────────────╮", "No left side │"]);
+        assert!(!lines(&output).last().unwrap().contains('─'));
+
+        let style = StyleConfig {
+            border: BorderStyle {
+                preset: BorderPreset::None,
+                ..BorderStyle::default()
+            },
+            ..StyleConfig::default()
+        };
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.make_output(&quote, &style);
+        // No blank rows or margin around the quote and its author
+        assert_eq!(output.output.len(), 2);
+        assert_eq!(lines(&output)[0], "No left side");
+
+        let mut output = Output::new(false, false, Centering::None, Some((12, 24)));
+        output.make_output(&quote, &style);
+        assert_eq!(lines(&output)[0], "No left side");
+        assert_eq!(output.width(), 12);
+    }
+
+    #[test]
+    fn test_border_labels() {
+        let mut quote = Quote::new("A quote in a labelled box".to_owned(), String::new());
+        quote.collection = Some("Letters".to_owned());
+        quote.metadata.source = Some("Moral Letters to Lucilius".to_owned());
+        let label = |text: &str, alignment| {
+            Some(BorderLabel {
+                text: text.to_owned(),
+                alignment,
+                ..BorderLabel::default()
+            })
+        };
+        let style = StyleConfig {
+            padding: (0, 0),
+            border: BorderStyle {
+                title: label("{source}, {date}", Alignment::Center),
+                footer: label("A footer much too long for the box", Alignment::Right),
+                ..BorderStyle::default()
+            },
+            ..StyleConfig::default()
+        };
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.date = NaiveDate::from_ymd_opt(2024, 3, 10);
+        output.make_output(&quote, &style);
+
+        let top = output.output[0].text();
+        let bottom = output.output.last().unwrap().text();
+        assert_eq!(top, "┌── Letters, 2024-03-10 ──┐");
+        assert_eq!(bottom, "└─ A footer much too lo… ─┘");
+        assert!(output.output[0]
+            .get_grouped()
+            .contains(&(" Letters, 2024-03-10 ".to_owned(), OutputCharType::Title)));
+
+        let widths = widths(&output, AmbiguousWidth::Narrow);
+        assert!(widths.iter().all(|w| *w == widths[0]), "{:?}", widths);
+
+        assert_eq!(
+            expand_label("{work} ({source})", &quote, None),
+            "Moral Letters to Lucilius (Letters)"
+        );
+        quote.collection = None;
+        assert_eq!(expand_label("[{source}]", &quote, None), "[]");
+    }
+
+    #[test]
+    fn test_center_in() {
+        let quote = Quote::new("A quote wider than its author".to_owned(), "Me".to_owned());
+        let style = StyleConfig::default();
+        let mut output = Output::new(false, false, Centering::Both, None);
+        output.make_output(&quote, &style);
+        let (width, height) = (
+            output.output[0].width(AmbiguousWidth::Narrow),
+            output.output.len(),
+        );
+
+        output.center_in(width + 11, height + 5);
+        assert_eq!(output.output.len(), height + 4);
+        assert_eq!(output.output[2].width(AmbiguousWidth::Narrow), 5 + width);
+        assert!(output.output[0].graphemes.is_empty());
+
+        // Too small a terminal leaves the block as it was
+        let mut output = Output::new(false, false, Centering::Both, None);
+        output.make_output(&quote, &style);
+        output.center_in(width - 1, 1);
+        assert_eq!(output.output.len(), height);
+        assert_eq!(output.output[0].width(AmbiguousWidth::Narrow), width);
+    }
+
+    #[test]
+    fn test_fits_terminal() {
+        let quote = Quote::new(
+            "A long quote which would be far wider than a narrow terminal pane".to_owned(),
+            "Someone With A Rather Long Name".to_owned(),
+        );
+        let style = StyleConfig::default();
+        for columns in [12, 30, 50] {
+            let mut output = Output::new(false, false, Centering::None, Some((columns, 24)));
+            output.make_output(&quote, &style);
+
+            let widths = widths(&output, AmbiguousWidth::Narrow);
+            assert!(widths.iter().all(|w| *w == widths[0]), "{:?}", widths);
+            assert!(widths[0] <= columns, "{} > {}", widths[0], columns);
+        }
+
+        // Padding goes before content width does
+        let quote = Quote::new("word ".repeat(40), String::new());
+        let mut output = Output::new(false, false, Centering::None, Some((84, 24)));
+        output.make_output(&quote, &style);
+        assert_eq!(output.padding.0, 1);
+        assert_eq!(output.content_width, 79);
+    }
+
+    #[test]
+    fn test_align() {
+        let narrow = AmbiguousWidth::Narrow;
+        assert_eq!(align("é", 4, Alignment::Center, narrow), " é  ");
+        assert_eq!(align("é", 4, Alignment::MiddleRight, narrow), "  é ");
+        assert_eq!(align("名", 4, Alignment::Right, narrow), "  名");
+        assert_eq!(align("toolong", 4, Alignment::Left, narrow), "toolong");
+    }
 }
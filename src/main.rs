@@ -1,11 +1,30 @@
-use clap::{arg, builder::EnumValueParser, command, value_parser, Arg, ArgAction, Command};
-use config::{Config, StyleConfig};
-use display::Output;
-use quotes::{get_quote, Quote, QuoteFile, QuotesFile};
-use render::render;
-use std::{fs::File, io::Write, path::PathBuf};
+use chrono::Utc;
+use clap::{command, value_parser, Arg, ArgAction, ArgMatches, Command};
+use config::Config;
+use config::StyleConfig;
+use display::{Centering, Output};
+use quotes::{
+    filter::{LengthFilter, LengthLimit, LengthUnit, DEFAULT_THRESHOLD},
+    fortune::{FortuneFile, IndexOrder},
+    history::History,
+    persisted::Persisted,
+    search::{Search, SearchField},
+    sources::{Offense, SourceSpec, Sources},
+    strfile::Strfile,
+    Quote, Selection,
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use render::{render, render_to_string, write_atomically};
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    process,
+};
+use terminal_size::{terminal_size, Height, Width};
 use wrappers::Wrappers;
 mod config;
+mod daily;
 mod display;
 mod quotes;
 mod render;
@@ -18,14 +37,164 @@ fn main() {
                 .short('f')
                 .long("fortune")
                 .action(ArgAction::SetTrue)
-                .help("Read installed fortunes from /usr/share/fortune"),
+                .help("Read installed fortunes instead of quotes when no sources are given"),
+        )
+        .arg(
+            Arg::new("sources")
+                .help("Files, directories or collections to read quotes from, each optionally preceded by a percentage chance, e.g. 30% funny wisdom")
+                .num_args(0..)
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("list-sources")
+                .long("list-sources")
+                .action(ArgAction::SetTrue)
+                .help("List the quote sources and their probabilities, then exit"),
+        )
+        .arg(
+            Arg::new("offensive")
+                .short('o')
+                .long("offensive")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("all")
+                .help("Only read offensive fortunes"),
+        )
+        .arg(
+            Arg::new("all")
+                .short('a')
+                .long("all")
+                .action(ArgAction::SetTrue)
+                .help("Read both offensive and regular fortunes"),
+        )
+        .arg(
+            Arg::new("match")
+                .short('m')
+                .long("match")
+                .help("Print every quote matching this regular expression")
+                .required(false)
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("ignore-case")
+                .short('i')
+                .long("ignore-case")
+                .action(ArgAction::SetTrue)
+                .requires("match")
+                .help("Ignore case when matching quotes"),
+        )
+        .arg(
+            Arg::new("search-in")
+                .long("search-in")
+                .help("Which part of each quote to match against")
+                .requires("match")
+                .default_value("both")
+                .value_parser(["quote", "author", "both"]),
+        )
+        .arg(
+            Arg::new("compact")
+                .long("compact")
+                .action(ArgAction::SetTrue)
+                .requires("match")
+                .help("List matching quotes one per line with the file they came from"),
+        )
+        .arg(
+            Arg::new("short")
+                .long("short")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("long")
+                .help("Only pick quotes no longer than the length threshold"),
+        )
+        .arg(
+            Arg::new("long")
+                .short('l')
+                .long("long")
+                .action(ArgAction::SetTrue)
+                .help("Only pick quotes longer than the length threshold"),
+        )
+        .arg(
+            Arg::new("length")
+                .short('n')
+                .long("length")
+                .help("The length threshold for --short and --long, 160 characters by default")
+                .required(false)
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("lines")
+                .long("lines")
+                .action(ArgAction::SetTrue)
+                .help("Measure the length threshold in lines at the style's content width"),
+        )
+        .arg(
+            Arg::new("daily")
+                .short('d')
+                .long("daily")
+                .action(ArgAction::SetTrue)
+                .help("Pick the same quote all day on every machine with the same collections"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .help("Pick quotes deterministically using this seed")
+                .required(false)
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("shuffle")
+                .long("shuffle")
+                .action(ArgAction::SetTrue)
+                .help("Show every quote from the sources before repeating any"),
+        )
+        .arg(
+            Arg::new("history-length")
+                .long("history-length")
+                .help("How many recently shown quotes to avoid, 0 to disable")
+                .required(false)
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("reset-history")
+                .long("reset-history")
+                .action(ArgAction::SetTrue)
+                .help("Forget which quotes have been shown, then exit"),
+        )
+        .arg(
+            Arg::new("equal")
+                .short('e')
+                .long("equal")
+                .action(ArgAction::SetTrue)
+                .help("Pick each quote collection with equal probability, regardless of size"),
+        )
+        .arg(
+            Arg::new("verbatim")
+                .long("verbatim")
+                .action(ArgAction::SetTrue)
+                .help("Keep the line breaks and spacing of every quote instead of rewrapping them"),
         )
         .arg(
             Arg::new("center")
                 .short('c')
                 .long("center")
-                .action(ArgAction::SetTrue)
-                .help("Center the output within the terminal"),
+                .value_name("DIRECTION")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("horizontal")
+                .value_parser(["horizontal", "both"])
+                .help("Center the output within the terminal, vertically too with 'both'"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .value_name("FILE")
+                .help("Replace this file with the output, such as /etc/motd, instead of printing it")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .help("Read the config from this file instead of the default location")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
         )
         .arg(
             Arg::new("style")
@@ -40,9 +209,10 @@ fn main() {
             Arg::new("wrapper")
                 .short('w')
                 .long("wrapper")
-                .help("Set the output wrapper")
+                .help("Wrap the output in one from the config, say, think, catsign, or the name or path of a .cow file, repeat to stack them")
                 .required(false)
-                .value_parser(EnumValueParser::<Wrappers>::new()),
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(String)),
         )
         .arg(
             Arg::new("no-colors")
@@ -56,23 +226,363 @@ fn main() {
                 .action(ArgAction::SetTrue)
                 .help("Disable attributes (e.g. bold) in the output"),
         )
+        .subcommand(
+            Command::new("index")
+                .about("Write a strfile compatible index for a fortune file")
+                .arg(
+                    Arg::new("file")
+                        .help("The fortune file to index")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("Where to write the index, defaults to the file with .dat appended")
+                        .required(false)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("delimiter")
+                        .short('c')
+                        .long("delimiter")
+                        .help("Use this delimiter instead of detecting it")
+                        .required(false)
+                        .value_parser(value_parser!(char)),
+                )
+                .arg(
+                    Arg::new("ordered")
+                        .short('o')
+                        .long("ordered")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("random")
+                        .help("Sort the cookies alphabetically"),
+                )
+                .arg(
+                    Arg::new("ignore-case")
+                        .short('i')
+                        .long("ignore-case")
+                        .action(ArgAction::SetTrue)
+                        .requires("ordered")
+                        .help("Ignore case when sorting the cookies"),
+                )
+                .arg(
+                    Arg::new("random")
+                        .short('r')
+                        .long("random")
+                        .action(ArgAction::SetTrue)
+                        .help("Shuffle the cookies"),
+                )
+                .arg(
+                    Arg::new("rotated")
+                        .short('x')
+                        .long("rotated")
+                        .action(ArgAction::SetTrue)
+                        .help("Mark the cookies as rot13 encoded"),
+                )
+                .arg(
+                    Arg::new("silent")
+                        .short('s')
+                        .long("silent")
+                        .action(ArgAction::SetTrue)
+                        .help("Don't print a summary of the index"),
+                ),
+        )
         .get_matches();
 
+    if let Some(("index", index_matches)) = matches.subcommand() {
+        write_index(index_matches);
+        return;
+    }
+    // Nothing else needs to be valid to reset the history
+    if matches.get_flag("reset-history") {
+        History::reset().unwrap_or_else(|e| exit_with_error(e));
+        return;
+    }
+
     let no_attrs = matches.get_flag("no-attrs");
     let no_colors = matches.get_flag("no-colors");
-    let center = matches.get_flag("center");
-    let wrapper = matches.get_one::<Wrappers>("wrapper");
-
-    let quote = quotes::fortune::FortuneFile::read(PathBuf::from("./art"))
-        .unwrap()
-        .get_quote()
-        .unwrap();
-    let mut output = Output::new(!no_colors, !no_attrs, center);
-    output.make_output(&quote, &StyleConfig::default());
-
-    if wrapper.is_some() {
-        output = wrapper.unwrap().wrap(output, &StyleConfig::default())
+    let center = match matches.get_one::<String>("center").map(String::as_str) {
+        Some("both") => Centering::Both,
+        Some(_) => Centering::Horizontal,
+        None => Centering::None,
+    };
+    let output_path = matches.get_one::<PathBuf>("output");
+    // A file isn't shown in any particular terminal, so it isn't fitted or centered to this one
+    let terminal = terminal_size()
+        .filter(|_| output_path.is_none())
+        .map(|(Width(columns), Height(rows))| (columns.into(), rows.into()));
+    let style_name = matches.get_one::<String>("style").unwrap();
+
+    let config = Config::load(matches.get_one::<PathBuf>("config").map(|p| p.as_path()))
+        .unwrap_or_else(|e| exit_with_error(e));
+    let style = config
+        .get_style(style_name)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let wrapper_names: Vec<&String> = match matches.get_many::<String>("wrapper") {
+        Some(names) => names.collect(),
+        None => style.wrappers.iter().collect(),
+    };
+    let wrappers: Vec<Wrappers> = wrapper_names
+        .into_iter()
+        .map(|name| Wrappers::find(name, &config).unwrap_or_else(|e| exit_with_error(e)))
+        .collect();
+
+    let selection = if matches.get_flag("equal") {
+        Selection::PerFile
+    } else {
+        Selection::Uniform
+    };
+    let specs = match matches.get_many::<String>("sources") {
+        Some(args) => {
+            SourceSpec::parse_list(&args.collect::<Vec<_>>()).unwrap_or_else(|e| exit_with_error(e))
+        }
+        None => SourceSpec::defaults(matches.get_flag("fortune")),
+    };
+    let offense = if matches.get_flag("offensive") {
+        Offense::Only
+    } else if matches.get_flag("all") {
+        Offense::Include
+    } else {
+        Offense::Exclude
+    };
+    let mut sources =
+        Sources::resolve(&specs, selection, offense).unwrap_or_else(|e| exit_with_error(e));
+    sources.verbatim = matches.get_flag("verbatim");
+    let mut sink = Sink::new(output_path);
+    if matches.get_flag("list-sources") {
+        sources
+            .list()
+            .lines()
+            .try_for_each(|line| sink.line(line))
+            .and_then(|_| sink.finish())
+            .unwrap_or_else(|e| exit_with_write_error(e, output_path));
+        return;
+    }
+
+    let length_limit = if matches.get_flag("short") {
+        Some(LengthLimit::Short)
+    } else if matches.get_flag("long") {
+        Some(LengthLimit::Long)
+    } else {
+        None
+    };
+    let length_filter = length_limit.map(|limit| LengthFilter {
+        limit,
+        threshold: matches
+            .get_one::<usize>("length")
+            .copied()
+            .unwrap_or(DEFAULT_THRESHOLD),
+        unit: if matches.get_flag("lines") {
+            LengthUnit::Lines(
+                style
+                    .content_width
+                    .resolve(terminal.map(|(columns, _)| columns)),
+            )
+        } else {
+            LengthUnit::Chars
+        },
+    });
+    let length_matches = |quote: &Quote| length_filter.is_none_or(|f| f.matches(quote));
+
+    // Only shown by border labels, so a bad timezone is left for --daily to complain about
+    let today = daily::quote_day(&config.daily, Utc::now()).ok();
+    let layout_quote = |quote: &Quote| {
+        let mut output = Output::new(!no_colors, !no_attrs, center, terminal);
+        output.date = today;
+        output.wrapped_width = wrappers::added_width(&wrappers);
+        output.make_output(quote, style);
+        wrappers::wrap_all(&wrappers, output, style)
+    };
+
+    if let Some(pattern) = matches.get_one::<String>("match") {
+        let field =
+            SearchField::from_name(matches.get_one::<String>("search-in").unwrap()).unwrap();
+        let search = Search::new(pattern, matches.get_flag("ignore-case"), field)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let found = sources
+            .search(&search)
+            .unwrap_or_else(|e| exit_with_error(e));
+        if found.is_empty() {
+            exit_with_error(format!("no quotes match '{}'", pattern));
+        }
+
+        for (path, quote) in found {
+            let written = if matches.get_flag("compact") {
+                sink.line(&format!(
+                    "{}: {}",
+                    path.display(),
+                    compact_quote(&quote, style)
+                ))
+            } else {
+                sink.quote(layout_quote(&quote))
+            };
+            written.unwrap_or_else(|e| exit_with_write_error(e, output_path));
+        }
+        sink.finish()
+            .unwrap_or_else(|e| exit_with_write_error(e, output_path));
+        return;
+    }
+
+    let daily = matches.get_flag("daily");
+    let seed = matches
+        .get_one::<String>("seed")
+        .or(config.daily.seed.as_ref().filter(|_| daily));
+    let seeded = daily || seed.is_some();
+    let mut rng = if seeded {
+        let day = match daily {
+            true => daily::quote_day(&config.daily, Utc::now())
+                .unwrap_or_else(|e| exit_with_error(e))
+                .to_string(),
+            false => String::new(),
+        };
+        let fingerprint = sources.fingerprint();
+        daily::seeded_rng(&[&day, seed.map_or("", |s| s.as_str()), &fingerprint])
+    } else {
+        ChaCha8Rng::from_entropy()
+    };
+
+    // Seeded quotes have to be the same everywhere, so they can't depend on what was shown before
+    let history_length = matches
+        .get_one::<usize>("history-length")
+        .copied()
+        .unwrap_or(config.history.length);
+    let shuffle = matches.get_flag("shuffle") || config.history.shuffle;
+    let use_history = !seeded && (history_length > 0 || shuffle);
+    let mut history = match use_history {
+        true => History::load(History::path().as_deref()),
+        false => History::default(),
+    };
+    let bag = shuffle.then(|| format!("{:016x}", daily::fnv1a(&[&sources.fingerprint()])));
+    let bag = bag.as_deref();
+
+    let mut pick = |predicate: &dyn Fn(&Quote) -> bool| {
+        sources
+            .get_quote_where(predicate, &mut rng)
+            .unwrap_or_else(|e| exit_with_error(e))
+    };
+    let mut quote = None;
+    if use_history {
+        quote = pick(&|q| length_matches(q) && history.is_fresh(q, bag));
+        // Quotes left in the bag which are the wrong length still keep it from being refilled
+        if let (None, Some(bag)) = (&quote, bag) {
+            if pick(&|q| history.is_fresh(q, Some(bag))).is_none() {
+                history.refill(bag);
+                quote = pick(&|q| length_matches(q) && history.is_fresh(q, Some(bag)));
+            }
+        }
+    }
+    let quote = quote
+        .or_else(|| pick(&length_matches))
+        .unwrap_or_else(|| match length_filter {
+            Some(filter) => exit_with_error(format!("no quotes are {}", filter)),
+            None => exit_with_error("no quotes found"),
+        });
+
+    if use_history {
+        history.record(&quote, bag, history_length);
+        history.save(History::path().as_deref());
+    }
+    sink.quote(layout_quote(&quote))
+        .and_then(|_| sink.finish())
+        .unwrap_or_else(|e| exit_with_write_error(e, output_path));
+}
+
+/// Where quotes are written. A file is only written once everything has rendered, and then
+/// atomically, so that nothing reading it sees it half written.
+enum Sink {
+    Stdout(io::StdoutLock<'static>),
+    File(PathBuf, String),
+}
+
+impl Sink {
+    fn new(path: Option<&PathBuf>) -> Self {
+        match path {
+            Some(path) => Sink::File(path.clone(), String::new()),
+            None => Sink::Stdout(io::stdout().lock()),
+        }
+    }
+
+    fn quote(&mut self, output: Output) -> io::Result<()> {
+        match self {
+            Sink::Stdout(stdout) => render(output, stdout),
+            Sink::File(_, content) => {
+                content.push_str(&render_to_string(output));
+                Ok(())
+            }
+        }
+    }
+
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        match self {
+            Sink::Stdout(stdout) => writeln!(stdout, "{}", line),
+            Sink::File(_, content) => {
+                content.push_str(line);
+                content.push('\n');
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Sink::Stdout(mut stdout) => stdout.flush(),
+            Sink::File(path, content) => write_atomically(&path, content.as_bytes()),
+        }
+    }
+}
+
+/// A quote on a single line, with its author if it has one.
+fn compact_quote(quote: &Quote, style: &StyleConfig) -> String {
+    let text = quote.quote.split_whitespace().collect::<Vec<_>>().join(" ");
+    if quote.author.is_empty() {
+        return text;
     }
+    format!("{} {}{}", text, style.author.prefix, quote.author)
+}
+
+fn write_index(matches: &ArgMatches) {
+    let path = matches.get_one::<PathBuf>("file").unwrap();
+    let output = matches
+        .get_one::<PathBuf>("output")
+        .cloned()
+        .unwrap_or_else(|| Strfile::index_path(path));
+    let order = if matches.get_flag("ordered") {
+        IndexOrder::Ordered {
+            ignore_case: matches.get_flag("ignore-case"),
+        }
+    } else if matches.get_flag("random") {
+        IndexOrder::Random
+    } else {
+        IndexOrder::Sequential
+    };
+
+    let index = FortuneFile::build_index(
+        path.clone(),
+        matches.get_one::<char>("delimiter").copied(),
+        order,
+        matches.get_flag("rotated"),
+    )
+    .and_then(|index| index.write(&output).map(|_| index))
+    .unwrap_or_else(|e| exit_with_error(format!("{}: {}", path.display(), e)));
 
-    render(output);
+    if !matches.get_flag("silent") {
+        println!("\"{}\" created", output.display());
+        println!("There were {} strings", index.numstr);
+        println!("Longest string: {} bytes", index.longlen);
+        println!("Shortest string: {} bytes", index.shortlen);
+    }
+}
+
+fn exit_with_error<E: std::fmt::Display>(e: E) -> ! {
+    eprintln!("inspire: {}", e);
+    process::exit(1)
+}
+
+fn exit_with_write_error(e: io::Error, path: Option<&PathBuf>) -> ! {
+    match path {
+        Some(path) => exit_with_error(format!("could not write {}: {}", path.display(), e)),
+        // Whatever was reading the output has stopped, like `head` does, which isn't an error
+        None if e.kind() == io::ErrorKind::BrokenPipe => process::exit(0),
+        None => exit_with_error(e),
+    }
 }
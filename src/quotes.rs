@@ -1,78 +1,99 @@
+pub mod cache;
+pub mod filter;
 pub mod fortune;
+pub mod history;
 pub mod inspire;
+pub mod persisted;
+pub mod search;
+pub mod sources;
+pub mod strfile;
 
+pub use inspire::QuotesFile;
+
+use crate::daily::fnv1a;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::{
-    ffi::OsStr,
-    fs::{self, DirEntry, File},
-    io::{BufRead, BufReader, Read, Write},
-    os::{self},
-    path::PathBuf,
-};
+use std::path::PathBuf;
 
 pub trait QuoteFile {
     fn read(path: PathBuf) -> std::io::Result<Self>
     where
         Self: Sized;
-    fn get_quote(self) -> Option<Quote>;
-}
-
-use rand::seq::{IteratorRandom, SliceRandom};
-use toml::to_string;
-
-pub fn get_quote() -> Quote {
-    let quote_files = fs::read_dir("/usr/share/inspire/quotes")
-        .unwrap()
-        .filter_map(|res| res.ok())
-        .map(|dir_entry| dir_entry.path())
-        .filter_map(|path| {
-            if path.extension().map_or(false, |ext| ext == "toml") {
-                return Some(path);
-            }
-            None
-        });
-
-    let file = quote_files.choose(&mut rand::thread_rng()).unwrap();
-    let mut quotes_file_content: String = String::new();
-    let quotes_file = File::open(file)
-        .unwrap()
-        .read_to_string(&mut quotes_file_content);
-    let quotes: QuotesFile = toml::from_str(&quotes_file_content).unwrap();
-    return quotes
-        .quotes
-        .choose(&mut rand::thread_rng())
-        .unwrap()
-        .clone();
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct QuotesFile {
-    pub quotes: Vec<Quote>,
+    /// The number of quotes in the file
+    fn count(&self) -> usize;
+    fn get_quote<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Quote>;
+    /// Every quote in the file, in the order they appear
+    fn into_quotes(self) -> std::io::Result<Vec<Quote>>;
 }
 
-impl QuotesFile {
-    pub fn split(&self, n: u32) -> Vec<Self> {
-        let quotes = self.quotes.clone();
-        let dst: Vec<&[Quote]> = quotes
-            .chunks((quotes.len() as f64 / n as f64) as usize)
-            .collect();
+pub const QUOTES_DIR: &str = "/usr/share/inspire/quotes";
+/// Where fortune files are installed, which differs between distributions
+pub const FORTUNE_DIRS: [&str; 3] = [
+    "/usr/share/fortune",
+    "/usr/share/games/fortunes",
+    "/usr/share/fortunes",
+];
 
-        let files = dst
-            .iter()
-            .map(|q| QuotesFile { quotes: q.to_vec() })
-            .collect();
-        return files;
-    }
+/// How a quote is picked from a source containing several files.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Selection {
+    /// Every quote is equally likely, regardless of which file it is in
+    Uniform,
+    /// Every file is equally likely, like fortune's `-e`
+    PerFile,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Quote {
     pub quote: String,
+    #[serde(default)]
     pub author: String,
+    /// The quote's line breaks and spacing are significant, so it must not be rewrapped
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub preformatted: bool,
+    #[serde(flatten)]
+    pub metadata: QuoteMetadata,
+    /// The name of the collection the quote was picked from
+    #[serde(skip)]
+    pub collection: Option<String>,
 }
 
 impl Quote {
     pub fn new(quote: String, author: String) -> Self {
-        Self { quote, author }
+        Self {
+            quote,
+            author,
+            preformatted: false,
+            metadata: QuoteMetadata::default(),
+            collection: None,
+        }
+    }
+
+    /// Identifies a quote by its content, so it keeps its ID when its file is reordered.
+    pub fn id(&self) -> String {
+        format!("{:016x}", fnv1a(&[&self.quote, &self.author]))
     }
 }
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Optional details about a quote, only available from native inspire collections.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct QuoteMetadata {
+    /// The work the quote is taken from
+    #[serde(alias = "work", skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
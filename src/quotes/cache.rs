@@ -0,0 +1,92 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    fortune::FortuneFile, persisted::Persisted, sources::SourceKind, QuoteFile, QuotesFile,
+};
+use crate::daily::fnv1a;
+
+const CACHE_DIR: &str = "counts";
+
+/// Remembers how many quotes each file of a source holds so that selection can be weighted by
+/// size without parsing every file on each run. Entries are refreshed whenever a file's
+/// modification time or length changes, and dropped once the file is no longer in the source.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct CountCache {
+    files: BTreeMap<String, FileSummary>,
+    /// The files summarised on this run
+    #[serde(skip)]
+    used: BTreeSet<String>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct FileSummary {
+    modified: u64,
+    len: u64,
+    pub count: usize,
+    /// The default weight from a collection's header
+    pub weight: Option<f64>,
+}
+
+impl Persisted for CountCache {
+    fn changed(&self) -> bool {
+        self.changed
+    }
+}
+
+impl CountCache {
+    /// Each source has a cache of its own, so that runs over one never read another's.
+    pub fn path(source: &Path) -> Option<PathBuf> {
+        let name = format!("{:016x}.toml", fnv1a(&[&source.to_string_lossy()]));
+        dirs::cache_dir().map(|d| d.join("inspire").join(CACHE_DIR).join(name))
+    }
+
+    pub fn summary(&mut self, path: &Path, kind: SourceKind) -> std::io::Result<FileSummary> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let key = path.to_string_lossy().into_owned();
+        self.used.insert(key.clone());
+
+        if let Some(cached) = self.files.get(&key) {
+            if cached.modified == modified && cached.len == metadata.len() {
+                return Ok(*cached);
+            }
+        }
+
+        let (count, weight) = match kind {
+            SourceKind::Collection => {
+                let file = QuotesFile::read(path.to_path_buf())?;
+                (file.count(), file.collection.weight)
+            }
+            SourceKind::Fortune => (FortuneFile::read(path.to_path_buf())?.count(), None),
+        };
+        let summary = FileSummary {
+            modified,
+            len: metadata.len(),
+            count,
+            weight,
+        };
+        self.files.insert(key, summary);
+        self.changed = true;
+        Ok(summary)
+    }
+
+    /// Drops the files which weren't summarised on this run.
+    pub fn prune(&mut self) {
+        let len = self.files.len();
+        self.files.retain(|path, _| self.used.contains(path));
+        self.changed |= self.files.len() != len;
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::Display;
+
+use textwrap::{wrap, Options};
+
+use super::Quote;
+use crate::config::Spacing;
+
+/// The threshold fortune uses for short quotes
+pub const DEFAULT_THRESHOLD: usize = 160;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LengthLimit {
+    /// At most the threshold, like fortune's `-s`
+    Short,
+    /// Over the threshold, like fortune's `-l`
+    Long,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LengthUnit {
+    Chars,
+    /// Lines once wrapped to the given content width
+    Lines(Spacing),
+}
+
+/// Restricts selection to quotes shorter or longer than a threshold. Only the quote itself is
+/// measured, not its author.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LengthFilter {
+    pub limit: LengthLimit,
+    pub threshold: usize,
+    pub unit: LengthUnit,
+}
+
+impl LengthFilter {
+    pub fn length(&self, quote: &Quote) -> usize {
+        match self.unit {
+            LengthUnit::Chars => quote.quote.chars().count(),
+            LengthUnit::Lines(_) if quote.preformatted => quote.quote.lines().count(),
+            LengthUnit::Lines(0) => quote.quote.lines().count(),
+            LengthUnit::Lines(width) => wrap(&quote.quote, Options::new(width)).len(),
+        }
+    }
+
+    pub fn matches(&self, quote: &Quote) -> bool {
+        let length = self.length(quote);
+        match self.limit {
+            LengthLimit::Short => length <= self.threshold,
+            LengthLimit::Long => length > self.threshold,
+        }
+    }
+}
+
+impl Display for LengthFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.unit {
+            LengthUnit::Chars => "characters",
+            LengthUnit::Lines(_) => "lines",
+        };
+        match self.limit {
+            LengthLimit::Short => write!(f, "{} {} or shorter", self.threshold, unit),
+            LengthLimit::Long => write!(f, "longer than {} {}", self.threshold, unit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_filter() {
+        let quote = Quote::new("one two three four".to_owned(), "Someone".to_owned());
+        let mut filter = LengthFilter {
+            limit: LengthLimit::Short,
+            threshold: 18,
+            unit: LengthUnit::Chars,
+        };
+        assert!(filter.matches(&quote));
+        filter.limit = LengthLimit::Long;
+        assert!(!filter.matches(&quote));
+
+        filter.unit = LengthUnit::Lines(8);
+        filter.threshold = 2;
+        assert_eq!(filter.length(&quote), 3);
+        assert!(filter.matches(&quote));
+    }
+}
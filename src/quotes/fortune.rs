@@ -1,15 +1,47 @@
+pub mod parser;
+
 use std::{
-    fs::File,
-    io::{BufRead, BufReader, Lines, Seek},
-    path::PathBuf,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
 };
 
-use super::{Quote, QuoteFile};
-use rand::seq::SliceRandom;
+use parser::{detect_delimiter, CookieParser, DEFAULT_DELIMITER};
+
+use super::{
+    strfile::{Strfile, STR_ROTATED},
+    Quote, QuoteFile,
+};
+use rand::{seq::SliceRandom, Rng};
 
 pub struct FortuneFile {
+    path: PathBuf,
     delimiter: char,
-    quotes: Vec<Quote>,
+    /// Whether the cookies are rot13 encoded, as offensive fortunes usually are
+    rotated: bool,
+    /// Whether every cookie keeps its line breaks, not just those which look preformatted
+    verbatim: bool,
+    cookies: Cookies,
+}
+
+/// The directory fortune installs offensive cookies to within each fortune directory
+pub const OFFENSIVE_DIR: &str = "off";
+
+pub enum IndexOrder {
+    /// Cookies keep the order they have in the file
+    Sequential,
+    /// Cookies are sorted as `strfile -o` sorts them, folding case like `-i` if asked to
+    Ordered {
+        ignore_case: bool,
+    },
+    Random,
+}
+
+enum Cookies {
+    /// Cookies are read on demand using the offsets in the strfile index
+    Indexed(Strfile),
+    /// The lines of every cookie, already decoded if the file is rotated
+    Parsed(Vec<Vec<String>>),
 }
 
 impl QuoteFile for FortuneFile {
@@ -17,74 +49,163 @@ impl QuoteFile for FortuneFile {
     where
         Self: Sized,
     {
-        let delimiter = Self::identify_delimiter(path.clone())?;
+        // An index which doesn't end where the file does is stale, so scan the file instead
+        if let Ok(index) = Strfile::read(&Strfile::index_path(&path)) {
+            if index.end() == fs::metadata(&path)?.len() {
+                return Ok(Self {
+                    path,
+                    delimiter: index.delimiter as char,
+                    rotated: index.flags & STR_ROTATED != 0,
+                    verbatim: false,
+                    cookies: Cookies::Indexed(index),
+                });
+            }
+        }
 
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let lines = reader.lines();
+        Self::scan(path)
+    }
 
-        let mut chunk: Vec<String> = vec![];
-        let mut quotes: Vec<Quote> = vec![];
+    fn count(&self) -> usize {
+        match &self.cookies {
+            Cookies::Indexed(index) => index.numstr as usize,
+            Cookies::Parsed(cookies) => cookies.len(),
+        }
+    }
 
-        for line in lines {
-            if line.is_err() {
-                continue;
-            }
-            let line_text = line.unwrap();
-            if line_text.trim() == delimiter.to_string() {
-                quotes.push(Self::process_chunk(&chunk));
-                chunk.clear();
-            } else {
-                chunk.push(line_text)
+    fn get_quote<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Quote> {
+        match &self.cookies {
+            Cookies::Indexed(index) => {
+                if index.numstr == 0 {
+                    return None;
+                }
+                let i = rng.gen_range(0..index.numstr as usize);
+                let mut chunk = index.read_cookie(&self.path, i).ok()?;
+                if self.rotated {
+                    chunk.iter_mut().for_each(|l| *l = rot13(l));
+                }
+                Some(Self::process_chunk(&chunk, self.verbatim))
             }
+            Cookies::Parsed(cookies) => cookies
+                .choose(rng)
+                .map(|chunk| Self::process_chunk(chunk, self.verbatim)),
         }
-
-        Ok(Self { delimiter, quotes })
     }
 
-    fn get_quote(self) -> Option<Quote> {
-        return self.quotes.choose(&mut rand::thread_rng()).cloned();
+    fn into_quotes(self) -> std::io::Result<Vec<Quote>> {
+        let cookies = match self.cookies {
+            // Reading every cookie through the index would reopen the file for each one
+            Cookies::Indexed(_) => {
+                Self::parse(&self.path, Some(self.delimiter as u8), self.rotated)?.0
+            }
+            Cookies::Parsed(cookies) => cookies,
+        };
+        Ok(cookies
+            .iter()
+            .map(|chunk| Self::process_chunk(chunk, self.verbatim))
+            .collect())
     }
 }
 
 impl FortuneFile {
-    fn identify_delimiter(path: PathBuf) -> std::io::Result<char> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let mut chars: Vec<char> = reader
-            .lines()
-            .into_iter()
-            .filter_map(|l| l.ok())
-            .filter_map(|l| {
-                let trimmed = l.trim();
-                if trimmed.len() == 1 {
-                    return Some(trimmed.to_owned());
-                }
-                return None;
-            })
-            .filter_map(|l| l.chars().next())
-            .collect();
-        chars.sort();
-        let mut unique_chars = chars.clone();
-        unique_chars.dedup();
-        if unique_chars.len() == 1 {
-            return Ok(unique_chars[0]);
+    /// Keeps the line breaks and spacing of every cookie rather than only those which look
+    /// preformatted.
+    pub fn verbatim(mut self, verbatim: bool) -> Self {
+        self.verbatim = verbatim;
+        self
+    }
+
+    /// Builds a strfile index for the fortune file at `path`, detecting its delimiter unless
+    /// one is given.
+    pub fn build_index(
+        path: PathBuf,
+        delimiter: Option<char>,
+        order: IndexOrder,
+        rotated: bool,
+    ) -> std::io::Result<Strfile> {
+        if delimiter.is_some_and(|d| !d.is_ascii()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the delimiter must be a single byte character",
+            ));
+        }
+
+        let content = fs::read(&path)?;
+        let delimiter = delimiter.map_or_else(|| detect_delimiter(&content), |d| d as u8);
+        let mut index = Strfile::build(&content, delimiter);
+        match order {
+            IndexOrder::Sequential => (),
+            IndexOrder::Ordered { ignore_case } => index.order(&content, ignore_case),
+            IndexOrder::Random => index.shuffle(),
+        }
+        if rotated {
+            index.flags |= STR_ROTATED;
         }
 
-        return Ok('%');
+        Ok(index)
     }
 
-    fn process_chunk(chunk: &Vec<String>) -> Quote {
+    /// Whether a file is offensive by fortune's convention of keeping them in their own directory.
+    pub fn is_offensive(path: &Path) -> bool {
+        path.parent()
+            .and_then(|p| p.file_name())
+            .is_some_and(|name| name == OFFENSIVE_DIR)
+    }
+
+    fn scan(path: PathBuf) -> std::io::Result<Self> {
+        // Without an index to say otherwise, assume offensive cookies are encoded
+        let rotated = Self::is_offensive(&path);
+        let (cookies, delimiter) = Self::parse(&path, None, rotated)?;
+
+        Ok(Self {
+            path,
+            delimiter: delimiter as char,
+            rotated,
+            verbatim: false,
+            cookies: Cookies::Parsed(cookies),
+        })
+    }
+
+    /// Parses the lines of every cookie in the file, returning them along with the delimiter
+    /// used.
+    fn parse(
+        path: &Path,
+        delimiter: Option<u8>,
+        rotated: bool,
+    ) -> std::io::Result<(Vec<Vec<String>>, u8)> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut parser = CookieParser::new(reader, path.to_path_buf(), delimiter);
+
+        let mut cookies = Vec::new();
+        for cookie in parser.by_ref() {
+            let mut lines = cookie?.lines;
+            if rotated {
+                lines.iter_mut().for_each(|l| *l = rot13(l));
+            }
+            cookies.push(lines);
+        }
+
+        Ok((cookies, parser.delimiter().unwrap_or(DEFAULT_DELIMITER)))
+    }
+
+    /// Splits a cookie into its quote and author. The quote is joined into a single paragraph
+    /// unless `verbatim` is set or the cookie looks preformatted, in which case it keeps its
+    /// line breaks and spacing.
+    pub fn process_chunk(chunk: &[String], verbatim: bool) -> Quote {
         let mut content = Vec::new();
         let mut author: Vec<String> = Vec::new();
         let mut unsure: Vec<String> = Vec::new();
         let mut after_author = false;
-        chunk.iter().enumerate().for_each(|(i, l)| {
+        chunk.iter().for_each(|l| {
             let trimmed = l.trim();
             if trimmed.is_empty() {
+                // Blank lines only matter between the lines of preformatted cookies
+                if !after_author {
+                    content.push(String::new());
+                }
                 return;
             }
-            let author_line = trimmed[0..2] == *"--";
+            // Rules of dashes are drawings rather than attributions
+            let author_line = trimmed.starts_with("--") && !trimmed.starts_with("---");
             if author_line || after_author {
                 if after_author && author_line {
                     content.append(&mut unsure);
@@ -102,26 +223,130 @@ impl FortuneFile {
                 .iter()
                 .map(|l| {
                     let trimmed = l.trim();
-                    if trimmed[0..2] == *"--" {
-                        return trimmed[2..].trim().to_owned();
-                    } else {
-                        return trimmed.to_owned();
+                    match trimmed.strip_prefix("--") {
+                        Some(author) => author.trim().to_owned(),
+                        None => trimmed.to_owned(),
                     }
                 })
                 .collect(),
         );
 
-        return Quote::new(content.join(" "), author.join(" "));
+        let preformatted = verbatim || looks_preformatted(&content);
+        let text = if preformatted {
+            let lines: Vec<&str> = content.iter().map(|l| l.trim_end()).collect();
+            lines.join("\n").trim_matches('\n').to_owned()
+        } else {
+            let lines: Vec<&str> = content
+                .iter()
+                .filter(|l| !l.is_empty())
+                .map(String::as_str)
+                .collect();
+            lines.join(" ")
+        };
+
+        let mut quote = Quote::new(text, author.join(" "));
+        quote.preformatted = preformatted;
+        quote
     }
 }
 
+/// Lines at most this many characters long read as verse rather than wrapped prose
+const SHORT_LINE: usize = 40;
+/// How many lines a cookie needs before short lines are taken to be verse
+const VERSE_LINES: usize = 4;
+/// Characters which ASCII art draws lines with
+const DRAWING_CHARS: &str = "_-=~*#+|/\\";
+/// How many times a drawing character repeats to make a line
+const DRAWING_RUN: usize = 4;
+
+/// Guesses whether a cookie's line breaks and spacing are significant, as with ASCII art,
+/// tables, dialogue and verse.
+fn looks_preformatted(lines: &[String]) -> bool {
+    let lines: Vec<&str> = lines
+        .iter()
+        .map(|l| l.trim_end())
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    let drawn = lines.iter().any(|l| is_drawing(l));
+    let aligned = lines.iter().filter(|l| is_aligned(l)).count() >= 2;
+    let verse = lines.len() >= VERSE_LINES && lines.iter().all(|l| l.chars().count() <= SHORT_LINE);
+
+    drawn || aligned || verse
+}
+
+/// Whether a line contains box drawing characters or a run of ASCII ones.
+fn is_drawing(line: &str) -> bool {
+    let mut run = 0;
+    let mut previous = None;
+    for c in line.chars() {
+        if ('\u{2500}'..='\u{259f}').contains(&c) {
+            return true;
+        }
+        run = if previous == Some(c) { run + 1 } else { 1 };
+        previous = Some(c);
+        if run >= DRAWING_RUN && DRAWING_CHARS.contains(c) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether a line is indented or spaced out to line up with the others. A single tab starting a
+/// paragraph doesn't count.
+fn is_aligned(line: &str) -> bool {
+    let text = line.trim_start();
+    let indent = &line[..line.len() - text.len()];
+    indent.starts_with("  ")
+        || indent.starts_with("\t\t")
+        || text.contains("   ")
+        || text.contains('\t')
+}
+
+pub fn rot13(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'a'..='m' | 'A'..='M' => (c as u8 + 13) as char,
+            'n'..='z' | 'N'..='Z' => (c as u8 - 13) as char,
+            _ => c,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_rot13() {
+        assert_eq!(rot13("Uryyb, Jbeyq! -- 42"), "Hello, World! -- 42");
+        assert_eq!(rot13(&rot13("Ünïcode stays")), "Ünïcode stays");
+    }
+
     #[test]
     fn test_split_file() {}
 
+    #[test]
+    fn test_crlf_through_index() {
+        let path = std::env::temp_dir().join(format!("inspire-crlf-{}", std::process::id()));
+        fs::write(&path, "one\r\n%\r\n \r\n%\r\ntwo\r\n%\r\nthree\r\n").unwrap();
+        let index = FortuneFile::build_index(path.clone(), None, IndexOrder::Sequential, false);
+        let index_path = Strfile::index_path(&path);
+        index.unwrap().write(&index_path).unwrap();
+
+        let file = FortuneFile::read(path.clone()).unwrap();
+        let Cookies::Indexed(index) = &file.cookies else {
+            panic!("the index wasn't used");
+        };
+        let quotes: Vec<String> = (0..file.count())
+            .map(|i| index.read_cookie(&path, i).unwrap().join(" "))
+            .collect();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&index_path).unwrap();
+
+        assert_eq!(quotes, vec!["one", "two", "three"]);
+    }
+
     #[test]
     fn test_process_chunk_author_twoline() {
         let chunk = vec![
@@ -130,7 +355,7 @@ mod tests {
             "\t\tNew Cynic's Calendar', 1905".to_string(),
         ];
 
-        let quote = FortuneFile::process_chunk(&chunk);
+        let quote = FortuneFile::process_chunk(&chunk, false);
         assert_eq!(
             quote,
             Quote::new(
@@ -141,6 +366,18 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_process_chunk_short_lines() {
+        let chunk = vec![
+            "I".to_owned(),
+            "é".to_owned(),
+            "-".to_owned(),
+            "--".to_owned(),
+        ];
+        let quote = FortuneFile::process_chunk(&chunk, false);
+        assert_eq!(quote, Quote::new("I é -".to_owned(), "".to_owned()));
+    }
+
     #[test]
     fn test_process_chunk_complicated() {
         let chunk = vec![
@@ -150,7 +387,58 @@ mod tests {
             "\t\t-- Robert Heinlein".to_owned(),
         ];
 
-        let quote = FortuneFile::process_chunk(&chunk);
+        let quote = FortuneFile::process_chunk(&chunk, false);
         assert_eq!(quote, Quote::new("A 'critic' is a man who creates nothing and thereby feels qualified to judge the work of creative men. There is logic in this; he is unbiased -- he hates all creative people equally.".to_owned(), "Robert Heinlein".to_owned()));
     }
+
+    #[test]
+    fn test_process_chunk_preformatted() {
+        let chunk: Vec<String> = ["  ____", " /    \\", "", " \\____/", "\t\t-- Someone"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+
+        let quote = FortuneFile::process_chunk(&chunk, false);
+        assert!(quote.preformatted);
+        assert_eq!(quote.quote, "  ____\n /    \\\n\n \\____/");
+        assert_eq!(quote.author, "Someone");
+    }
+
+    #[test]
+    fn test_process_chunk_verbatim() {
+        let chunk = vec![
+            "A fool-proof method for sculpting an elephant: first, get a huge block of".to_owned(),
+            "marble; then you chip away everything that doesn't look like an elephant.".to_owned(),
+        ];
+
+        assert!(!FortuneFile::process_chunk(&chunk, false).preformatted);
+        let quote = FortuneFile::process_chunk(&chunk, true);
+        assert!(quote.preformatted);
+        assert_eq!(quote.quote, chunk.join("\n"));
+    }
+
+    #[test]
+    fn test_looks_preformatted() {
+        let lines = |ls: &[&str]| ls.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+
+        // Paragraphs starting with a tab are still prose
+        assert!(!looks_preformatted(&lines(&[
+            "\tA hard-luck actor who appeared in one colossal disaster after another",
+            "finally got a break, a broken leg to be exact.  Someone pointed out that it's",
+        ])));
+        assert!(looks_preformatted(&lines(&["+------+", "| box  |"])));
+        assert!(looks_preformatted(&lines(&["┌─┐", "└─┘"])));
+        assert!(looks_preformatted(&lines(&[
+            "Q: \"Herr Mozart, can you give me any",
+            "   suggestions as to how to get started?\"",
+            "A: \"A symphony is a very complex musical form, perhaps you should begin with",
+            "   some simple lieder and work your way up to a symphony.\"",
+        ])));
+        assert!(looks_preformatted(&lines(&[
+            "Roses are red,",
+            "Violets are blue,",
+            "This line is short,",
+            "And so are these two.",
+        ])));
+    }
 }
@@ -0,0 +1,385 @@
+use std::{collections::VecDeque, fmt::Display, io::BufRead, path::PathBuf};
+
+/// The delimiter assumed for files which never use one
+pub const DEFAULT_DELIMITER: u8 = b'%';
+
+/// The raw lines of one cookie, without line endings.
+#[derive(Debug, PartialEq)]
+pub struct Cookie {
+    /// The line the cookie starts on, counting from one, if lines are being counted
+    pub line: Option<usize>,
+    pub lines: Vec<String>,
+}
+
+impl Cookie {
+    fn is_blank(&self) -> bool {
+        self.lines.iter().all(|l| is_blank_line(l.as_bytes()))
+    }
+}
+
+/// Reads cookies one at a time from a fortune file in a single pass.
+///
+/// Cookies are separated by lines holding only the delimiter. When the delimiter isn't known,
+/// lines are held back until it is settled: `%` as soon as a lone `%` line turns up, or when
+/// two different characters have been seen on lines of their own, and otherwise the only one
+/// seen by the end of the file. Empty cookies are skipped, the last cookie doesn't need a
+/// delimiter after it, CRLF line endings are accepted and lines which aren't UTF-8 are read as
+/// Latin-1.
+pub struct CookieParser<R> {
+    reader: R,
+    path: PathBuf,
+    delimiter: Option<u8>,
+    line: Option<usize>,
+    /// Lines read while the delimiter was being settled, which are yet to be split into cookies
+    pending: VecDeque<Result<String, ParseErrorKind>>,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl<R: BufRead> CookieParser<R> {
+    /// Parses a whole file from its start.
+    pub fn new(reader: R, path: PathBuf, delimiter: Option<u8>) -> Self {
+        Self {
+            reader,
+            path,
+            delimiter,
+            line: Some(0),
+            pending: VecDeque::new(),
+            buffer: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Parses from part way through a file, where line numbers are unknown.
+    pub fn from_offset(reader: R, path: PathBuf, delimiter: u8) -> Self {
+        Self {
+            line: None,
+            ..Self::new(reader, path, Some(delimiter))
+        }
+    }
+
+    /// The delimiter, once it is settled.
+    pub fn delimiter(&self) -> Option<u8> {
+        self.delimiter
+    }
+
+    fn error(&mut self, kind: ParseErrorKind) -> Option<Result<Cookie, ParseError>> {
+        self.done = true;
+        Some(Err(ParseError {
+            path: self.path.clone(),
+            line: self.line,
+            kind,
+        }))
+    }
+
+    /// Reads the next line from the file, or `None` at its end.
+    fn read_line(&mut self) -> Option<Result<String, ParseErrorKind>> {
+        self.buffer.clear();
+        match self.reader.read_until(b'\n', &mut self.buffer) {
+            Ok(0) => None,
+            Err(e) => Some(Err(ParseErrorKind::Io(e))),
+            Ok(_) if self.buffer.contains(&0) => Some(Err(ParseErrorKind::Binary)),
+            Ok(_) => Some(Ok(decode_line(&self.buffer))),
+        }
+    }
+
+    /// Reads ahead until the delimiter is settled, holding on to the lines read.
+    fn settle_delimiter(&mut self) {
+        let mut candidate = None;
+        while self.delimiter.is_none() {
+            let Some(line) = self.read_line() else {
+                break;
+            };
+            self.delimiter = match &line {
+                Ok(line) => match line.as_bytes().trim_ascii() {
+                    [DEFAULT_DELIMITER] => Some(DEFAULT_DELIMITER),
+                    &[byte] if byte.is_ascii_punctuation() => match candidate {
+                        Some(other) if other != byte => Some(DEFAULT_DELIMITER),
+                        _ => {
+                            candidate = Some(byte);
+                            None
+                        }
+                    },
+                    _ => None,
+                },
+                // Nothing after an error is parsed, so there's no more to go on
+                Err(_) => Some(candidate.unwrap_or(DEFAULT_DELIMITER)),
+            };
+            self.pending.push_back(line);
+        }
+        self.delimiter = self.delimiter.or(candidate).or(Some(DEFAULT_DELIMITER));
+    }
+
+    /// The next line of the file, taking those held back while settling the delimiter first.
+    fn next_line(&mut self) -> Option<Result<String, ParseErrorKind>> {
+        let line = self.pending.pop_front().or_else(|| self.read_line());
+        if line.is_some() {
+            self.line = self.line.map(|l| l + 1);
+        }
+        line
+    }
+}
+
+impl<R: BufRead> Iterator for CookieParser<R> {
+    type Item = Result<Cookie, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.delimiter.is_none() {
+            self.settle_delimiter();
+        }
+        let delimiter = self.delimiter.unwrap_or(DEFAULT_DELIMITER);
+
+        let mut cookie = Cookie {
+            line: self.line.map(|l| l + 1),
+            lines: Vec::new(),
+        };
+        loop {
+            let line = match self.next_line() {
+                None => {
+                    self.done = true;
+                    return (!cookie.is_blank()).then_some(Ok(cookie));
+                }
+                Some(Err(kind)) => return self.error(kind),
+                Some(Ok(line)) => line,
+            };
+
+            if !is_delimiter_line(line.as_bytes(), delimiter) {
+                cookie.lines.push(line);
+            } else if cookie.is_blank() {
+                cookie.line = self.line.map(|l| l + 1);
+                cookie.lines.clear();
+            } else {
+                return Some(Ok(cookie));
+            }
+        }
+    }
+}
+
+/// The delimiter a fortune file uses, settled the same way `CookieParser` settles it.
+pub fn detect_delimiter(content: &[u8]) -> u8 {
+    let mut parser = CookieParser::new(content, PathBuf::new(), None);
+    parser.settle_delimiter();
+    parser.delimiter.unwrap_or(DEFAULT_DELIMITER)
+}
+
+/// Whether a line separates cookies. Surrounding whitespace, including the `\r` of a CRLF line
+/// ending, is ignored.
+pub fn is_delimiter_line(line: &[u8], delimiter: u8) -> bool {
+    line.trim_ascii() == [delimiter]
+}
+
+/// Whether a line is only whitespace. Cookies made of nothing else are skipped.
+pub fn is_blank_line(line: &[u8]) -> bool {
+    line.trim_ascii().is_empty()
+}
+
+/// Decodes a line without its line ending, falling back to Latin-1 for older fortune files
+/// which aren't UTF-8.
+fn decode_line(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(line) => line.to_owned(),
+        Err(_) => bytes.iter().map(|b| *b as char).collect(),
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    Io(std::io::Error),
+    /// The file contains NUL bytes, so is probably an index or some other binary file
+    Binary,
+}
+
+impl ParseError {
+    /// The error without the path, for when the path is already reported alongside it.
+    fn description(&self) -> String {
+        let kind = match &self.kind {
+            ParseErrorKind::Io(e) => e.to_string(),
+            ParseErrorKind::Binary => String::from("binary data, not a fortune file"),
+        };
+        match self.line {
+            Some(line) => format!("line {}: {}", line, kind),
+            None => kind,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.description())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for std::io::Error {
+    fn from(value: ParseError) -> Self {
+        let kind = match &value.kind {
+            ParseErrorKind::Io(e) => e.kind(),
+            ParseErrorKind::Binary => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, value.description())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quotes::fortune::FortuneFile;
+    use rand::{seq::SliceRandom, Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn parse(content: &[u8]) -> Vec<Vec<String>> {
+        CookieParser::new(content, PathBuf::from("test"), None)
+            .map(|c| c.unwrap().lines)
+            .collect()
+    }
+
+    fn lines(cookies: &[&[&str]]) -> Vec<Vec<String>> {
+        cookies
+            .iter()
+            .map(|c| c.iter().map(|l| l.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_trailing_cookie() {
+        assert_eq!(parse(b"one\n%\ntwo"), lines(&[&["one"], &["two"]]));
+        assert_eq!(parse(b"one\n%\ntwo\n"), lines(&[&["one"], &["two"]]));
+        assert_eq!(parse(b"one\n%\ntwo\n%\n"), lines(&[&["one"], &["two"]]));
+    }
+
+    #[test]
+    fn test_crlf_and_empty_cookies() {
+        assert_eq!(
+            parse(b"%\r\none\r\n%\r\n%\r\n\r\n%\r\ntwo\r\n"),
+            lines(&[&["one"], &["two"]])
+        );
+        assert!(parse(b"").is_empty());
+        assert!(parse(b"%\n%\n  \n").is_empty());
+    }
+
+    #[test]
+    fn test_detects_delimiter() {
+        let content = b"I\n-\nsay\n-\nhi";
+        let mut parser = CookieParser::new(&content[..], PathBuf::new(), None);
+        let cookies: Vec<Cookie> = parser.by_ref().map(|c| c.unwrap()).collect();
+        assert_eq!(parser.delimiter(), Some(b'-'));
+        assert_eq!(detect_delimiter(content), b'-');
+        assert_eq!(cookies[1].lines, vec!["say"]);
+        assert_eq!(cookies[2].line, Some(5));
+        assert_eq!(detect_delimiter(b"no delimiters"), DEFAULT_DELIMITER);
+    }
+
+    #[test]
+    fn test_prefers_percent_delimiter() {
+        // A lone dash in the first cookie doesn't stop `%` from being the delimiter
+        let content = b"first\n-\nsecond\n%\nthird";
+        assert_eq!(detect_delimiter(content), b'%');
+        assert_eq!(
+            parse(content),
+            lines(&[&["first", "-", "second"], &["third"]])
+        );
+        // Neither of two other characters is picked over the default
+        assert_eq!(detect_delimiter(b"a\n-\nb\n*\nc"), DEFAULT_DELIMITER);
+    }
+
+    #[test]
+    fn test_non_utf8_is_latin1() {
+        assert_eq!(
+            parse(b"caf\xe9\n%\n\xc3\xa9t\xc3\xa9"),
+            lines(&[&["café"], &["été"]])
+        );
+    }
+
+    #[test]
+    fn test_binary_error_line() {
+        let mut parser = CookieParser::new(&b"one\n%\ntwo\x00\n"[..], PathBuf::from("f"), None);
+        assert!(parser.next().unwrap().is_ok());
+        let error = parser.next().unwrap().unwrap_err();
+        assert_eq!(error.line, Some(3));
+        assert_eq!(
+            error.to_string(),
+            "f: line 3: binary data, not a fortune file"
+        );
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn test_fuzz_never_panics() {
+        let alphabet: &[&[u8]] = &[
+            b"%",
+            b"\n",
+            b"\r\n",
+            b"-",
+            b"--",
+            b" ",
+            b"\t",
+            b"a",
+            b"I",
+            b"\xc3\xa9",
+            b"\xff",
+            b"\xe2\x94",
+            b"\x00",
+            b"+",
+        ];
+        let mut rng = ChaCha8Rng::seed_from_u64(12);
+        for _ in 0..2000 {
+            let len = rng.gen_range(0..64);
+            let content: Vec<u8> = (0..len)
+                .flat_map(|_| alphabet.choose(&mut rng).unwrap().iter().copied())
+                .collect();
+            for cookie in CookieParser::new(&content[..], PathBuf::new(), None).flatten() {
+                FortuneFile::process_chunk(&cookie.lines, false);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fuzz_round_trip() {
+        let words = [
+            "one",
+            "two",
+            "--",
+            "-- Author",
+            "é",
+            "%%",
+            "  indented",
+            "x",
+        ];
+        let mut rng = ChaCha8Rng::seed_from_u64(34);
+        for _ in 0..500 {
+            let cookies: Vec<Vec<String>> = (0..rng.gen_range(0..6))
+                .map(|_| {
+                    (0..rng.gen_range(1..4))
+                        .map(|_| words.choose(&mut rng).unwrap().to_string())
+                        .collect()
+                })
+                .collect();
+            let ending = if rng.gen() { "\r\n" } else { "\n" };
+            let mut content = cookies
+                .iter()
+                .map(|c| c.join(ending))
+                .collect::<Vec<_>>()
+                .join(&format!("{}%{}", ending, ending));
+            if rng.gen() {
+                content += &format!("{}%{}", ending, ending);
+            }
+
+            let mut parser = CookieParser::new(content.as_bytes(), PathBuf::new(), Some(b'%'));
+            let parsed: Vec<Vec<String>> = parser.by_ref().map(|c| c.unwrap().lines).collect();
+            assert_eq!(parsed, cookies, "{:?}", content);
+        }
+    }
+}
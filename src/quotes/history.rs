@@ -0,0 +1,137 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs,
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{persisted::Persisted, Quote};
+
+const HISTORY_FILE: &str = "history.toml";
+
+/// The quotes shown on previous runs, used to avoid repeating them. Quotes are recorded by
+/// their IDs.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct History {
+    /// Recently shown quotes, oldest first
+    recent: VecDeque<String>,
+    /// The quotes taken out of the shuffle bag, keyed by the bag's name. Only the bag last used
+    /// is kept, since changing the sources starts a new one
+    bags: BTreeMap<String, BTreeSet<String>>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl Persisted for History {
+    fn changed(&self) -> bool {
+        self.changed
+    }
+}
+
+impl History {
+    /// The history lives in the state directory, or the local data directory on platforms
+    /// without one.
+    pub fn path() -> Option<PathBuf> {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|d| d.join("inspire").join(HISTORY_FILE))
+    }
+
+    pub fn reset() -> std::io::Result<()> {
+        match Self::path().map(fs::remove_file) {
+            Some(Err(e)) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether a quote is still in `bag`, or without a bag whether it hasn't been shown
+    /// recently. A bag alone decides, so that every quote in it is shown before it's refilled.
+    pub fn is_fresh(&self, quote: &Quote, bag: Option<&str>) -> bool {
+        let id = quote.id();
+        match bag {
+            Some(bag) => !self.bags.get(bag).is_some_and(|shown| shown.contains(&id)),
+            None => !self.recent.contains(&id),
+        }
+    }
+
+    /// Puts every quote back into `bag`.
+    pub fn refill(&mut self, bag: &str) {
+        self.changed |= self.bags.remove(bag).is_some();
+    }
+
+    /// Records a quote as shown, keeping at most `length` recent quotes.
+    pub fn record(&mut self, quote: &Quote, bag: Option<&str>, length: usize) {
+        let id = quote.id();
+        if let Some(bag) = bag {
+            self.bags.retain(|name, _| name == bag);
+            self.bags
+                .entry(bag.to_owned())
+                .or_default()
+                .insert(id.clone());
+        }
+        self.recent.retain(|recent| *recent != id);
+        self.recent.push_back(id);
+        while self.recent.len() > length {
+            self.recent.pop_front();
+        }
+        self.changed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(text: &str) -> Quote {
+        Quote::new(text.to_owned(), String::new())
+    }
+
+    #[test]
+    fn test_recent_length() {
+        let mut history = History::default();
+        for text in ["a", "b", "c"] {
+            history.record(&quote(text), None, 2);
+        }
+        assert!(history.is_fresh(&quote("a"), None));
+        assert!(!history.is_fresh(&quote("b"), None));
+        assert!(!history.is_fresh(&quote("c"), None));
+
+        history.record(&quote("d"), None, 0);
+        assert!(history.is_fresh(&quote("d"), None));
+    }
+
+    #[test]
+    fn test_shuffle_bag() {
+        let mut history = History::default();
+        history.record(&quote("a"), Some("bag"), 0);
+        assert!(!history.is_fresh(&quote("a"), Some("bag")));
+        assert!(history.is_fresh(&quote("a"), Some("other")));
+        assert!(history.is_fresh(&quote("a"), None));
+
+        history.refill("bag");
+        assert!(history.is_fresh(&quote("a"), Some("bag")));
+
+        // A new bag replaces the old one rather than piling up beside it
+        history.record(&quote("a"), Some("bag"), 0);
+        history.record(&quote("b"), Some("new"), 0);
+        assert!(history.is_fresh(&quote("a"), Some("bag")));
+        assert_eq!(history.bags.len(), 1);
+    }
+
+    #[test]
+    fn test_shuffle_bag_ignores_recent() {
+        let mut history = History::default();
+        for text in ["a", "b", "c"] {
+            history.record(&quote(text), Some("bag"), 10);
+        }
+        history.refill("bag");
+        history.record(&quote("a"), Some("bag"), 10);
+
+        // "b" and "c" were shown recently, but they're back in the refilled bag
+        assert!(!history.is_fresh(&quote("a"), Some("bag")));
+        assert!(history.is_fresh(&quote("b"), Some("bag")));
+        assert!(!history.is_fresh(&quote("b"), None));
+    }
+}
@@ -0,0 +1,157 @@
+use std::{fs, path::PathBuf};
+
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use super::{Quote, QuoteFile};
+
+/// A native inspire collection, a TOML file with an optional `[collection]` header followed by
+/// `[[quotes]]` tables. Files without a header are read as an unnamed collection.
+#[derive(Serialize, Deserialize, Default)]
+pub struct QuotesFile {
+    #[serde(default, skip_serializing_if = "CollectionHeader::is_empty")]
+    pub collection: CollectionHeader,
+    pub quotes: Vec<Quote>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct CollectionHeader {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The percentage chance of picking this collection when no weight is given for it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+}
+
+impl CollectionHeader {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl QuoteFile for QuotesFile {
+    fn read(path: PathBuf) -> std::io::Result<Self>
+    where
+        Self: Sized,
+    {
+        let content = fs::read_to_string(&path)?;
+        toml::from_str(&content).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    fn count(&self) -> usize {
+        self.quotes.len()
+    }
+
+    fn get_quote<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Quote> {
+        self.quotes.choose(rng).cloned()
+    }
+
+    fn into_quotes(self) -> std::io::Result<Vec<Quote>> {
+        Ok(self.quotes)
+    }
+}
+
+#[allow(dead_code)]
+impl QuotesFile {
+    /// Splits the collection into `n` files whose sizes differ by at most one quote.
+    pub fn split(&self, n: u32) -> Vec<Self> {
+        let n = (n as usize).clamp(1, self.quotes.len().max(1));
+        let (size, remainder) = (self.quotes.len() / n, self.quotes.len() % n);
+
+        let mut quotes = self.quotes.iter();
+        (0..n)
+            .map(|i| {
+                let len = size + usize::from(i < remainder);
+                QuotesFile {
+                    collection: self.collection.clone(),
+                    quotes: quotes.by_ref().take(len).cloned().collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_legacy_file() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("quotes/quotes.toml");
+        let file = QuotesFile::read(path).unwrap();
+        assert!(file.collection.is_empty());
+        assert_eq!(
+            file.quotes[0],
+            Quote::new(
+                "As long as you think your past is bad you must be improving".to_owned(),
+                "Louis C.K".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn test_split_even() {
+        let file = QuotesFile {
+            collection: CollectionHeader::default(),
+            quotes: (0..10)
+                .map(|i| Quote::new(i.to_string(), String::new()))
+                .collect(),
+        };
+        let sizes: Vec<usize> = file.split(4).iter().map(|f| f.quotes.len()).collect();
+        assert_eq!(sizes, vec![3, 3, 2, 2]);
+        assert_eq!(file.split(20).len(), 10);
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let file: QuotesFile = toml::from_str(
+            r#"
+            [collection]
+            name = "stoics"
+            description = "Mostly Marcus"
+            weight = 20.0
+
+            [[quotes]]
+            quote = "The impediment to action advances action."
+            author = "Marcus Aurelius"
+            work = "Meditations"
+            year = 180
+            tags = ["stoicism", "action"]
+            language = "en"
+
+            [[quotes]]
+            quote = "Anonymous and undated"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(file.collection.name.as_deref(), Some("stoics"));
+        assert_eq!(file.collection.weight, Some(20.0));
+        let metadata = &file.quotes[0].metadata;
+        assert_eq!(metadata.source.as_deref(), Some("Meditations"));
+        assert_eq!(metadata.year, Some(180));
+        assert_eq!(metadata.tags, vec!["stoicism", "action"]);
+        assert_eq!(metadata.url, None);
+        assert_eq!(file.quotes[1].author, "");
+    }
+
+    #[test]
+    fn test_serialize_without_metadata_is_unchanged() {
+        let file = QuotesFile {
+            collection: CollectionHeader::default(),
+            quotes: vec![Quote::new("Quote".to_owned(), "Author".to_owned())],
+        };
+        assert_eq!(
+            toml::to_string(&file).unwrap(),
+            "[[quotes]]\nquote = \"Quote\"\nauthor = \"Author\"\n"
+        );
+    }
+}
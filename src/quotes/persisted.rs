@@ -0,0 +1,35 @@
+use std::{fs, path::Path};
+
+use crate::render::write_atomically;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// State kept between runs in a TOML file, such as the count cache and the history. Losing the
+/// file only costs some work or lets quotes repeat, so reading and writing it never fails. The
+/// path is `None` on platforms without anywhere to keep the file.
+pub trait Persisted: Serialize + DeserializeOwned + Default {
+    /// Whether anything changed since the file was loaded.
+    fn changed(&self) -> bool;
+
+    /// Loads the file, starting from the default if it is missing or unreadable.
+    fn load(path: Option<&Path>) -> Self {
+        path.and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the file back if anything changed. It is replaced atomically so that runs at the
+    /// same time can't leave it corrupted.
+    fn save(&self, path: Option<&Path>) {
+        if !self.changed() {
+            return;
+        }
+        let Some(path) = path else {
+            return;
+        };
+        if let (Some(dir), Ok(content)) = (path.parent(), toml::to_string(self)) {
+            let _ =
+                fs::create_dir_all(dir).and_then(|_| write_atomically(path, content.as_bytes()));
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use regex::{Regex, RegexBuilder};
+
+use super::Quote;
+
+/// Which parts of a quote a search looks at.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SearchField {
+    Quote,
+    Author,
+    Both,
+}
+
+impl SearchField {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "quote" => Some(SearchField::Quote),
+            "author" => Some(SearchField::Author),
+            "both" => Some(SearchField::Both),
+            _ => None,
+        }
+    }
+}
+
+pub struct Search {
+    regex: Regex,
+    field: SearchField,
+}
+
+impl Search {
+    pub fn new(pattern: &str, ignore_case: bool, field: SearchField) -> Result<Self, regex::Error> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .build()?;
+        Ok(Self { regex, field })
+    }
+
+    pub fn matches(&self, quote: &Quote) -> bool {
+        match self.field {
+            SearchField::Quote => self.regex.is_match(&quote.quote),
+            SearchField::Author => self.regex.is_match(&quote.author),
+            SearchField::Both => {
+                self.regex.is_match(&quote.quote) || self.regex.is_match(&quote.author)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_fields() {
+        let quote = Quote::new(
+            "A 'critic' is a man who creates nothing".to_owned(),
+            "Robert Heinlein".to_owned(),
+        );
+
+        let search = Search::new("heinlein", true, SearchField::Both).unwrap();
+        assert!(search.matches(&quote));
+        let search = Search::new("heinlein", false, SearchField::Both).unwrap();
+        assert!(!search.matches(&quote));
+        let search = Search::new("Heinlein", false, SearchField::Quote).unwrap();
+        assert!(!search.matches(&quote));
+        let search = Search::new(r"^A '\w+'", false, SearchField::Quote).unwrap();
+        assert!(search.matches(&quote));
+    }
+}
@@ -0,0 +1,682 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    Rng,
+};
+
+use super::{
+    cache::CountCache,
+    fortune::{FortuneFile, OFFENSIVE_DIR},
+    persisted::Persisted,
+    search::Search,
+    Quote, QuoteFile, QuotesFile, Selection, FORTUNE_DIRS, QUOTES_DIR,
+};
+
+/// How many random quotes `get_quote_where` tries before reading every file
+const RANDOM_ATTEMPTS: usize = 100;
+/// Extensions of the files fortune installs next to its cookie files
+const IGNORED_EXTENSIONS: [&str; 3] = ["dat", "u8", "pos"];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SourceKind {
+    /// A native inspire collection
+    Collection,
+    Fortune,
+}
+
+impl SourceKind {
+    pub fn of(path: &Path) -> Self {
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            SourceKind::Collection
+        } else {
+            SourceKind::Fortune
+        }
+    }
+}
+
+/// Which of the offensive collections kept in `off` directories are read.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Offense {
+    /// Only collections outside `off` directories, the default
+    Exclude,
+    /// Only collections in `off` directories
+    Only,
+    Include,
+}
+
+impl Offense {
+    /// The directories to look in for quotes in `dir`.
+    fn dirs(&self, dir: &Path) -> Vec<PathBuf> {
+        match self {
+            Offense::Exclude => vec![dir.to_path_buf()],
+            Offense::Only => vec![dir.join(OFFENSIVE_DIR)],
+            Offense::Include => vec![dir.to_path_buf(), dir.join(OFFENSIVE_DIR)],
+        }
+    }
+}
+
+/// A source as given on the command line, e.g. `30% funny`.
+#[derive(Debug, PartialEq)]
+pub struct SourceSpec {
+    pub percent: Option<f64>,
+    pub name: String,
+}
+
+impl SourceSpec {
+    /// Parses fortune style source arguments, where a percentage applies to the source after
+    /// it and may either be its own argument or prefix the source.
+    pub fn parse_list<S: AsRef<str>>(args: &[S]) -> Result<Vec<Self>, SourceError> {
+        let mut specs = Vec::new();
+        let mut percent = None;
+
+        for arg in args.iter().map(|a| a.as_ref()) {
+            let name = match arg.split_once('%') {
+                Some((number, name)) => {
+                    if percent.is_some() {
+                        return Err(SourceError::MissingSource(arg.to_owned()));
+                    }
+                    percent = Some(parse_percent(number)?);
+                    name
+                }
+                None => arg,
+            };
+            if name.is_empty() {
+                continue;
+            }
+            specs.push(Self {
+                percent: percent.take(),
+                name: name.to_owned(),
+            });
+        }
+
+        match percent {
+            Some(percent) => Err(SourceError::MissingSource(format!("{}%", percent))),
+            None => Ok(specs),
+        }
+    }
+
+    /// The installed quotes, or the installed fortunes if `fortune` is set.
+    pub fn defaults(fortune: bool) -> Vec<Self> {
+        let name = if fortune {
+            FORTUNE_DIRS
+                .iter()
+                .find(|d| Path::new(d).is_dir())
+                .unwrap_or(&FORTUNE_DIRS[0])
+        } else {
+            QUOTES_DIR
+        };
+        vec![Self {
+            percent: None,
+            name: name.to_string(),
+        }]
+    }
+}
+
+fn parse_percent(number: &str) -> Result<f64, SourceError> {
+    match number.parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+        _ => Err(SourceError::InvalidPercent(format!("{}%", number))),
+    }
+}
+
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub kind: SourceKind,
+    pub count: usize,
+    /// The percentage chance of this file being picked
+    pub percent: f64,
+}
+
+impl SourceFile {
+    /// Reads the file so that quotes can be drawn from it. When `verbatim` is set every quote
+    /// keeps its line breaks and spacing.
+    pub fn open(&self, verbatim: bool) -> std::io::Result<OpenFile> {
+        let (file, name) = match self.kind {
+            SourceKind::Collection => {
+                let file = QuotesFile::read(self.path.clone())?;
+                let name = file.collection.name.clone();
+                (OpenQuotes::Collection(file), name)
+            }
+            SourceKind::Fortune => {
+                let file = FortuneFile::read(self.path.clone())?.verbatim(verbatim);
+                (OpenQuotes::Fortune(file), None)
+            }
+        };
+        Ok(OpenFile {
+            file,
+            collection: self.collection(name),
+            verbatim,
+        })
+    }
+
+    pub fn quotes(&self, verbatim: bool) -> std::io::Result<Vec<Quote>> {
+        self.open(verbatim)?.into_quotes()
+    }
+
+    /// The name of the collection the file holds, from its header or else the file's name.
+    fn collection(&self, name: Option<String>) -> String {
+        name.unwrap_or_else(|| {
+            self.path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+}
+
+/// A source file which has been read, so that any number of quotes can be drawn from it without
+/// reading it again.
+pub struct OpenFile {
+    file: OpenQuotes,
+    collection: String,
+    verbatim: bool,
+}
+
+enum OpenQuotes {
+    Collection(QuotesFile),
+    Fortune(FortuneFile),
+}
+
+impl OpenFile {
+    /// Picks a quote at random.
+    pub fn get_quote<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Quote> {
+        let quote = match &self.file {
+            OpenQuotes::Collection(file) => file.get_quote(rng),
+            OpenQuotes::Fortune(file) => file.get_quote(rng),
+        };
+        quote.map(|q| label(q, &self.collection, self.verbatim))
+    }
+
+    pub fn into_quotes(self) -> std::io::Result<Vec<Quote>> {
+        let quotes = match self.file {
+            OpenQuotes::Collection(file) => file.into_quotes()?,
+            OpenQuotes::Fortune(file) => file.into_quotes()?,
+        };
+        Ok(quotes
+            .into_iter()
+            .map(|q| label(q, &self.collection, self.verbatim))
+            .collect())
+    }
+}
+
+fn label(mut quote: Quote, collection: &str, verbatim: bool) -> Quote {
+    quote.preformatted |= verbatim;
+    quote.collection = Some(collection.to_owned());
+    quote
+}
+
+pub struct Source {
+    pub name: String,
+    /// The percentage given on the command line or in a collection header
+    pub weight: Option<f64>,
+    /// The percentage chance of a file from this source being picked
+    pub percent: f64,
+    pub is_dir: bool,
+    pub files: Vec<SourceFile>,
+}
+
+impl Source {
+    fn single(path: PathBuf, kind: SourceKind, count: usize, weight: f64) -> Self {
+        Self {
+            name: path.display().to_string(),
+            weight: Some(weight),
+            percent: 0.0,
+            is_dir: false,
+            files: vec![SourceFile {
+                path,
+                kind,
+                count,
+                percent: 0.0,
+            }],
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.files.iter().map(|f| f.count).sum()
+    }
+
+    /// How much of the unassigned percentage this source should get relative to the others.
+    fn share(&self, selection: Selection) -> usize {
+        match selection {
+            Selection::Uniform => self.count(),
+            Selection::PerFile => self.files.iter().filter(|f| f.count > 0).count(),
+        }
+    }
+}
+
+pub struct Sources {
+    pub sources: Vec<Source>,
+    /// Every quote keeps its line breaks and spacing, not just those which look preformatted
+    pub verbatim: bool,
+}
+
+impl Sources {
+    pub fn resolve(
+        specs: &[SourceSpec],
+        selection: Selection,
+        offense: Offense,
+    ) -> Result<Self, SourceError> {
+        let mut sources = Vec::new();
+
+        for spec in specs {
+            let path = find(&spec.name, offense)
+                .ok_or_else(|| SourceError::NotFound(spec.name.clone()))?;
+            let is_dir = path.is_dir();
+            let paths = if is_dir {
+                files_in(&path, offense).map_err(|e| SourceError::Io(path.clone(), e))?
+            } else {
+                vec![path.clone()]
+            };
+
+            let cache_path = CountCache::path(&path);
+            let mut cache = CountCache::load(cache_path.as_deref());
+            let mut weight = spec.percent;
+            let mut files = Vec::new();
+            for path in paths {
+                let kind = SourceKind::of(&path);
+                let summary = cache
+                    .summary(&path, kind)
+                    .map_err(|e| SourceError::Io(path.clone(), e))?;
+
+                match (spec.percent, summary.weight) {
+                    // Collections with their own weight are split out of unweighted directories
+                    (None, Some(header_weight)) if is_dir => {
+                        sources.push(Source::single(path, kind, summary.count, header_weight));
+                        continue;
+                    }
+                    (None, Some(header_weight)) => weight = Some(header_weight),
+                    _ => (),
+                }
+                files.push(SourceFile {
+                    path,
+                    kind,
+                    count: summary.count,
+                    percent: 0.0,
+                });
+            }
+
+            cache.prune();
+            cache.save(cache_path.as_deref());
+
+            sources.push(Source {
+                name: path.display().to_string(),
+                weight,
+                percent: 0.0,
+                is_dir,
+                files,
+            });
+        }
+
+        let mut sources = Self {
+            sources,
+            verbatim: false,
+        };
+        sources.assign_percents(selection)?;
+        Ok(sources)
+    }
+
+    /// Splits whatever percentage the weighted sources leave over between the unweighted ones by
+    /// size, then splits each source's percentage between its files.
+    fn assign_percents(&mut self, selection: Selection) -> Result<(), SourceError> {
+        let assigned: f64 = self.sources.iter().filter_map(|s| s.weight).sum();
+        if assigned > 100.0 {
+            return Err(SourceError::OverAllocated(assigned));
+        }
+
+        let unweighted: usize = self
+            .sources
+            .iter()
+            .filter(|s| s.weight.is_none())
+            .map(|s| s.share(selection))
+            .sum();
+        // Without any unweighted sources to take up the rest, scale the weights up to 100%
+        let scale = if unweighted == 0 && assigned > 0.0 {
+            100.0 / assigned
+        } else {
+            1.0
+        };
+
+        for source in &mut self.sources {
+            source.percent = match source.weight {
+                Some(weight) => weight * scale,
+                None if unweighted > 0 => {
+                    (100.0 - assigned) * source.share(selection) as f64 / unweighted as f64
+                }
+                None => 0.0,
+            };
+
+            let share = source.share(selection);
+            for file in &mut source.files {
+                let file_share = match selection {
+                    Selection::Uniform => file.count,
+                    Selection::PerFile => usize::from(file.count > 0),
+                };
+                file.percent = if share == 0 {
+                    0.0
+                } else {
+                    source.percent * file_share as f64 / share as f64
+                };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Picks a quote satisfying `predicate`, each file being picked by its percentage and each
+    /// quote within it equally. A few quotes are drawn at random first, falling back to every
+    /// quote when the predicate rarely holds. Each file is read at most once, however many draws
+    /// land on it.
+    pub fn get_quote_where<P: Fn(&Quote) -> bool, R: Rng + ?Sized>(
+        &self,
+        predicate: P,
+        rng: &mut R,
+    ) -> Result<Option<Quote>, SourceError> {
+        let files: Vec<&SourceFile> = self.sources.iter().flat_map(|s| &s.files).collect();
+        let Ok(distribution) = WeightedIndex::new(files.iter().map(|f| f.percent)) else {
+            return Ok(None);
+        };
+        let open = |file: &SourceFile| {
+            file.open(self.verbatim)
+                .map_err(|e| SourceError::Io(file.path.clone(), e))
+        };
+
+        let mut opened: Vec<Option<OpenFile>> = files.iter().map(|_| None).collect();
+        for _ in 0..RANDOM_ATTEMPTS {
+            let i = distribution.sample(rng);
+            let file = match &mut opened[i] {
+                Some(file) => file,
+                unopened => unopened.insert(open(files[i])?),
+            };
+            if let Some(quote) = file.get_quote(rng).filter(|q| predicate(q)) {
+                return Ok(Some(quote));
+            }
+        }
+
+        let mut candidates = Vec::new();
+        let mut weights = Vec::new();
+        for (file, opened) in files.into_iter().zip(opened) {
+            if file.percent == 0.0 {
+                continue;
+            }
+            let opened = match opened {
+                Some(opened) => opened,
+                None => open(file)?,
+            };
+            let quotes = opened
+                .into_quotes()
+                .map_err(|e| SourceError::Io(file.path.clone(), e))?;
+            let weight = file.percent / quotes.len() as f64;
+            for quote in quotes.into_iter().filter(|q| predicate(q)) {
+                candidates.push(quote);
+                weights.push(weight);
+            }
+        }
+
+        let Ok(distribution) = WeightedIndex::new(weights) else {
+            return Ok(None);
+        };
+        Ok(Some(candidates.swap_remove(distribution.sample(rng))))
+    }
+
+    /// Describes the files in the sources, their sizes and odds, so that seeded selection only
+    /// agrees between machines with the same collections.
+    pub fn fingerprint(&self) -> String {
+        let mut fingerprint = String::new();
+        for file in self.sources.iter().flat_map(|s| &s.files) {
+            let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            fingerprint += &format!("{}:{}:{:.6};", name, file.count, file.percent);
+        }
+        fingerprint
+    }
+
+    /// Every quote matching `search` in every file of every source, along with its file.
+    pub fn search(&self, search: &Search) -> Result<Vec<(&Path, Quote)>, SourceError> {
+        let mut matches = Vec::new();
+        for file in self.sources.iter().flat_map(|s| &s.files) {
+            let quotes = file
+                .quotes(self.verbatim)
+                .map_err(|e| SourceError::Io(file.path.clone(), e))?;
+            matches.extend(
+                quotes
+                    .into_iter()
+                    .filter(|q| search.matches(q))
+                    .map(|q| (file.path.as_path(), q)),
+            );
+        }
+        Ok(matches)
+    }
+
+    /// Lists the sources and the files in them with their chance of being picked, like
+    /// `fortune -f`.
+    pub fn list(&self) -> String {
+        let mut list = String::new();
+        for source in &self.sources {
+            list += &format!("{:6.2}% {}\n", source.percent, source.name);
+            if !source.is_dir {
+                continue;
+            }
+            for file in &source.files {
+                let name = file.path.strip_prefix(&source.name).unwrap_or(&file.path);
+                list += &format!("    {:6.2}% {}\n", file.percent, name.display());
+            }
+        }
+        list
+    }
+}
+
+/// Resolves a source name to a path, either directly or as the name of a collection, fortune
+/// file or directory in the install locations.
+fn find(name: &str, offense: Offense) -> Option<PathBuf> {
+    let path = PathBuf::from(name);
+    if path.exists() {
+        return Some(path);
+    }
+
+    let search_dirs = std::iter::once(QUOTES_DIR).chain(FORTUNE_DIRS);
+    for dir in search_dirs.flat_map(|d| offense.dirs(Path::new(d))) {
+        for candidate in [dir.join(format!("{}.toml", name)), dir.join(name)] {
+            if candidate.exists() {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+/// The quote files directly inside `dir` or its `off` directory, sorted so that listings are
+/// stable. A missing `off` directory is treated as empty.
+fn files_in(dir: &Path, offense: Offense) -> std::io::Result<Vec<PathBuf>> {
+    let mut entries = Vec::new();
+    for subdir in offense.dirs(dir) {
+        match fs::read_dir(&subdir) {
+            Ok(read) => entries.extend(read),
+            Err(e) if subdir != dir && e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut files: Vec<PathBuf> = entries
+        .into_iter()
+        .filter_map(|res| res.ok())
+        .map(|dir_entry| dir_entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let hidden = path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+            let ignored = path
+                .extension()
+                .is_some_and(|ext| IGNORED_EXTENSIONS.iter().any(|i| ext == *i));
+            !hidden && !ignored
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    NotFound(String),
+    Io(PathBuf, std::io::Error),
+    InvalidPercent(String),
+    /// A percentage which isn't followed by a source
+    MissingSource(String),
+    OverAllocated(f64),
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceError::NotFound(name) => write!(f, "no quote source named '{}'", name),
+            SourceError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            SourceError::InvalidPercent(percent) => {
+                write!(f, "'{}' is not a percentage between 0 and 100", percent)
+            }
+            SourceError::MissingSource(percent) => {
+                write!(f, "percentage {} is not followed by a source", percent)
+            }
+            SourceError::OverAllocated(total) => {
+                write!(f, "source percentages add up to {}%, more than 100%", total)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(weight: Option<f64>, counts: &[usize]) -> Source {
+        Source {
+            name: String::new(),
+            weight,
+            percent: 0.0,
+            is_dir: counts.len() > 1,
+            files: counts
+                .iter()
+                .map(|count| SourceFile {
+                    path: PathBuf::new(),
+                    kind: SourceKind::Collection,
+                    count: *count,
+                    percent: 0.0,
+                })
+                .collect(),
+        }
+    }
+
+    fn percents(sources: &Sources) -> Vec<Vec<f64>> {
+        sources
+            .sources
+            .iter()
+            .map(|s| s.files.iter().map(|f| f.percent).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let specs = SourceSpec::parse_list(&["30%", "funny", "wisdom", "20%art"]).unwrap();
+        assert_eq!(
+            specs,
+            vec![
+                SourceSpec {
+                    percent: Some(30.0),
+                    name: "funny".to_owned()
+                },
+                SourceSpec {
+                    percent: None,
+                    name: "wisdom".to_owned()
+                },
+                SourceSpec {
+                    percent: Some(20.0),
+                    name: "art".to_owned()
+                },
+            ]
+        );
+        assert!(SourceSpec::parse_list(&["30%"]).is_err());
+        assert!(SourceSpec::parse_list(&["30%", "40%", "art"]).is_err());
+        assert!(SourceSpec::parse_list(&["130%", "art"]).is_err());
+    }
+
+    #[test]
+    fn test_assign_uniform() {
+        let mut sources = Sources {
+            sources: vec![source(None, &[3, 300, 0]), source(None, &[97])],
+            verbatim: false,
+        };
+        sources.assign_percents(Selection::Uniform).unwrap();
+        assert_eq!(percents(&sources), vec![vec![0.75, 75.0, 0.0], vec![24.25]]);
+    }
+
+    #[test]
+    fn test_assign_weighted() {
+        let mut sources = Sources {
+            sources: vec![
+                source(Some(30.0), &[10]),
+                source(None, &[1, 3]),
+                source(None, &[4]),
+            ],
+            verbatim: false,
+        };
+        sources.assign_percents(Selection::PerFile).unwrap();
+        assert_eq!(
+            percents(&sources),
+            vec![vec![30.0], vec![70.0 / 3.0, 70.0 / 3.0], vec![70.0 / 3.0]]
+        );
+    }
+
+    #[test]
+    fn test_assign_scales_weights() {
+        let mut sources = Sources {
+            sources: vec![source(Some(10.0), &[1]), source(Some(30.0), &[1])],
+            verbatim: false,
+        };
+        sources.assign_percents(Selection::Uniform).unwrap();
+        assert_eq!(percents(&sources), vec![vec![25.0], vec![75.0]]);
+
+        let mut sources = Sources {
+            sources: vec![source(Some(60.0), &[1]), source(Some(60.0), &[1])],
+            verbatim: false,
+        };
+        assert!(sources.assign_percents(Selection::Uniform).is_err());
+    }
+
+    #[test]
+    fn test_get_quote_where() {
+        let path = std::env::temp_dir().join(format!("inspire-where-{}", std::process::id()));
+        let mut content: String = (0..500).map(|i| format!("hay {}\n%\n", i)).collect();
+        content += "needle\n";
+        std::fs::write(&path, content).unwrap();
+        let sources = Sources {
+            sources: vec![Source {
+                name: String::new(),
+                weight: None,
+                percent: 100.0,
+                is_dir: false,
+                files: vec![SourceFile {
+                    path: path.clone(),
+                    kind: SourceKind::Fortune,
+                    count: 501,
+                    percent: 100.0,
+                }],
+            }],
+            verbatim: false,
+        };
+        let mut rng = crate::daily::seeded_rng(&["where"]);
+
+        let quote = sources.get_quote_where(|q| q.quote == "needle", &mut rng);
+        assert_eq!(quote.unwrap().unwrap().quote, "needle");
+        assert!(sources
+            .get_quote_where(|_| false, &mut rng)
+            .unwrap()
+            .is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
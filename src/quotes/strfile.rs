@@ -0,0 +1,276 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use super::fortune::parser::{is_blank_line, is_delimiter_line, CookieParser};
+use rand::seq::SliceRandom;
+
+/// The cookies are stored in a random order
+pub const STR_RANDOM: u32 = 0x1;
+/// The cookies are stored in alphabetical order
+pub const STR_ORDERED: u32 = 0x2;
+/// The cookies are rot13 encoded
+pub const STR_ROTATED: u32 = 0x4;
+
+const VERSION: u32 = 2;
+const HEADER_LEN: u64 = 24;
+/// strfile(1) reads lines with a fixed size `fgets` buffer, so longer lines are seen in pieces
+const LINE_BUFFER: usize = 255;
+
+/// The header and offset table of a strfile(1) `.dat` index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strfile {
+    pub version: u32,
+    pub numstr: u32,
+    pub longlen: u32,
+    pub shortlen: u32,
+    pub flags: u32,
+    pub delimiter: u8,
+    /// One offset per cookie followed by the offset of the end of the file
+    pub offsets: Vec<u64>,
+}
+
+impl Strfile {
+    /// The path strfile(1) writes the index for `path` to, i.e. `path` with `.dat` appended.
+    pub fn index_path(path: &Path) -> PathBuf {
+        let mut index: OsString = path.as_os_str().to_owned();
+        index.push(".dat");
+        PathBuf::from(index)
+    }
+
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        let mut header = [0; HEADER_LEN as usize];
+        file.read_exact(&mut header)?;
+        let field = |i: usize| u32::from_be_bytes(header[i * 4..i * 4 + 4].try_into().unwrap());
+
+        let numstr = field(1);
+        let count = numstr as u64 + 1;
+        // Most implementations store 32 bit offsets but some BSDs have moved to 64 bit ones
+        let width = match len.checked_sub(HEADER_LEN) {
+            Some(rest) if rest == count * 4 => 4,
+            Some(rest) if rest == count * 8 => 8,
+            _ => return Err(invalid_data("offset table does not match the string count")),
+        };
+
+        let mut table = Vec::new();
+        file.read_to_end(&mut table)?;
+        let offsets = table
+            .chunks_exact(width)
+            .map(|c| match width {
+                4 => u32::from_be_bytes(c.try_into().unwrap()) as u64,
+                _ => u64::from_be_bytes(c.try_into().unwrap()),
+            })
+            .collect();
+
+        Ok(Self {
+            version: field(0),
+            numstr,
+            longlen: field(2),
+            shortlen: field(3),
+            flags: field(4),
+            delimiter: header[20],
+            offsets,
+        })
+    }
+
+    /// Indexes the cookies in `content` the same way strfile(1) does. Delimiters and empty
+    /// cookies are found as `CookieParser` finds them, so that reading through the index gives
+    /// the same cookies as parsing the file.
+    pub fn build(content: &[u8], delimiter: u8) -> Self {
+        let mut index = Self {
+            version: VERSION,
+            numstr: 0,
+            longlen: 0,
+            shortlen: u32::MAX,
+            flags: 0,
+            delimiter,
+            offsets: vec![0],
+        };
+
+        let mut pos = 0;
+        let mut last_off = 0;
+        let mut blank = true;
+        let pieces = content
+            .split_inclusive(|b| *b == b'\n')
+            .flat_map(|line| line.chunks(LINE_BUFFER));
+        for piece in pieces {
+            pos += piece.len() as u64;
+            if is_delimiter_line(piece, delimiter) {
+                if !blank {
+                    index.add_cookie(pos, pos - last_off - piece.len() as u64);
+                }
+                last_off = pos;
+                blank = true;
+            } else {
+                blank &= is_blank_line(piece);
+            }
+        }
+        // The last cookie doesn't need a delimiter after it
+        if !blank {
+            index.add_cookie(pos, pos - last_off);
+        }
+        index.numstr = index.offsets.len() as u32 - 1;
+
+        index
+    }
+
+    fn add_cookie(&mut self, end: u64, length: u64) {
+        let length = length as u32;
+        self.offsets.push(end);
+        self.longlen = self.longlen.max(length);
+        self.shortlen = self.shortlen.min(length);
+    }
+
+    /// Sorts the cookies as strfile(1) does, comparing their bytes from the first alphanumeric
+    /// one onwards and only folding case when asked to.
+    pub fn order(&mut self, content: &[u8], ignore_case: bool) {
+        let end = self.offsets.pop().unwrap_or(0);
+        let delimiter = self.delimiter;
+        self.offsets.sort_by_cached_key(|offset| {
+            // An offset can point at empty cookies which come before the one it's for
+            content[*offset as usize..]
+                .split_inclusive(|b| *b == b'\n')
+                .skip_while(|line| is_blank_line(line) || is_delimiter_line(line, delimiter))
+                .take_while(|line| !is_delimiter_line(line, delimiter))
+                .flatten()
+                .skip_while(|b| !b.is_ascii_alphanumeric())
+                .map(|b| match ignore_case {
+                    true => b.to_ascii_lowercase(),
+                    false => *b,
+                })
+                .collect::<Vec<u8>>()
+        });
+        self.offsets.push(end);
+        self.flags |= STR_ORDERED;
+    }
+
+    pub fn shuffle(&mut self) {
+        let numstr = self.numstr as usize;
+        self.offsets[..numstr].shuffle(&mut rand::thread_rng());
+        self.flags |= STR_RANDOM;
+    }
+
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN as usize + self.offsets.len() * 4);
+        for field in [
+            self.version,
+            self.numstr,
+            self.longlen,
+            self.shortlen,
+            self.flags,
+        ] {
+            bytes.extend_from_slice(&field.to_be_bytes());
+        }
+        bytes.extend_from_slice(&[self.delimiter, 0, 0, 0]);
+        for offset in &self.offsets {
+            let offset = u32::try_from(*offset)
+                .map_err(|_| invalid_data("file is too large for a strfile index"))?;
+            bytes.extend_from_slice(&offset.to_be_bytes());
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let bytes = self.to_bytes()?;
+        File::create(path)?.write_all(&bytes)
+    }
+
+    /// The offset of the end of the indexed file.
+    pub fn end(&self) -> u64 {
+        self.offsets.last().copied().unwrap_or(0)
+    }
+
+    /// Reads the lines of the cookie at `index` from the indexed file.
+    pub fn read_cookie(&self, path: &Path, index: usize) -> std::io::Result<Vec<String>> {
+        if index >= self.numstr as usize {
+            return Err(invalid_data("cookie index out of range"));
+        }
+
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(self.offsets[index]))?;
+        let reader = BufReader::new(file);
+
+        // Empty cookies leave their delimiter at the start of the next cookie, which the parser
+        // skips over
+        let mut parser = CookieParser::from_offset(reader, path.to_path_buf(), self.delimiter);
+        match parser.next() {
+            Some(cookie) => Ok(cookie?.lines),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn art_path() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("art")
+    }
+
+    #[test]
+    fn test_build_matches_strfile() {
+        let content = std::fs::read(art_path()).unwrap();
+        let expected = std::fs::read(Strfile::index_path(&art_path())).unwrap();
+        let index = Strfile::build(&content, b'%');
+        assert_eq!(index.to_bytes().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_build_trailing_and_empty_cookies() {
+        let index = Strfile::build(b"%\none\n%\n%\ntwo two\n", b'%');
+        assert_eq!(index.numstr, 2);
+        assert_eq!(index.offsets, vec![0, 8, 18]);
+        assert_eq!(index.longlen, 8);
+        assert_eq!(index.shortlen, 4);
+    }
+
+    #[test]
+    fn test_order() {
+        let content = b"Zebra\n%\n-- apple\n%\nMango\n%\nman-go\n%\n";
+        let mut index = Strfile::build(content, b'%');
+        index.order(content, false);
+        // Only leading punctuation is skipped, and capitals sort before lower case
+        assert_eq!(index.offsets, vec![19, 0, 8, 27, 36]);
+        assert_eq!(index.flags, STR_ORDERED);
+
+        index.order(content, true);
+        assert_eq!(index.offsets, vec![8, 27, 19, 0, 36]);
+    }
+
+    #[test]
+    fn test_read_header() {
+        let strfile = Strfile::read(&Strfile::index_path(&art_path())).unwrap();
+        assert_eq!(strfile.version, 2);
+        assert_eq!(strfile.numstr, 474);
+        assert_eq!(strfile.offsets.len(), 475);
+        assert_eq!(strfile.delimiter, b'%');
+        assert_eq!(strfile.end(), std::fs::metadata(art_path()).unwrap().len());
+    }
+
+    #[test]
+    fn test_read_cookie() {
+        let strfile = Strfile::read(&Strfile::index_path(&art_path())).unwrap();
+        let first = strfile.read_cookie(&art_path(), 0).unwrap();
+        assert_eq!(
+            first[0],
+            "7:30, Channel 5: The Bionic Dog (Action/Adventure)"
+        );
+        let last = strfile.read_cookie(&art_path(), 473).unwrap();
+        assert_eq!(
+            last.last().unwrap(),
+            "    -- https://en.wikiquote.org/wiki/Last_Action_Hero"
+        );
+    }
+}
@@ -1,13 +1,59 @@
-use nu_ansi_term::{AnsiString, AnsiStrings, Color, Style};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    process,
+};
+
+use nu_ansi_term::Style;
 
 use crate::display::{Output, OutputCharType, OutputString, OutputStyles};
 
-pub fn render(output: Output) {
+/// Writes the output to `writer`, one line at a time.
+pub fn render<W: Write + ?Sized>(mut output: Output, writer: &mut W) -> io::Result<()> {
+    // Without a terminal there's nothing to center within
+    if let Some((columns, rows)) = output.terminal {
+        output.center_in(columns, rows);
+    }
+
     let styles = output.styles.unwrap_or_default();
     let filtered_styles = filter_styles(styles, output.color, output.attrs);
+    let mut painter = Painter::new(writer);
     for line in output.output {
-        render_line(line, &filtered_styles);
+        render_line(line, &filtered_styles, &mut painter)?;
     }
+    painter.finish()
+}
+
+pub fn render_to_string(output: Output) -> String {
+    let mut rendered = Vec::new();
+    // Writing to a vector can't fail, and everything written came from strings
+    render(output, &mut rendered).expect("rendering to memory failed");
+    String::from_utf8(rendered).expect("rendered output isn't UTF-8")
+}
+
+/// Replaces the file at `path` with `content` by writing a temporary file beside it and renaming
+/// it into place, so that nothing reading the file ever sees it half written.
+pub fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the output must be a file"))?;
+    let temporary =
+        path.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), process::id()));
+
+    let write = || {
+        let mut file = File::create(&temporary)?;
+        // Keep the permissions of the file being replaced, such as a world readable motd
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&temporary, path)
+    };
+    write().inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
 }
 
 fn filter_styles(mut styles: OutputStyles, color: bool, attrs: bool) -> OutputStyles {
@@ -17,12 +63,16 @@ fn filter_styles(mut styles: OutputStyles, color: bool, attrs: bool) -> OutputSt
         remove_colors(&mut styles.padding);
         remove_colors(&mut styles.border);
         remove_colors(&mut styles.content);
+        remove_colors(&mut styles.title);
+        remove_colors(&mut styles.footer);
     }
     if !attrs {
         remove_attributes(&mut styles.author);
         remove_attributes(&mut styles.padding);
         remove_attributes(&mut styles.border);
         remove_attributes(&mut styles.content);
+        remove_attributes(&mut styles.title);
+        remove_attributes(&mut styles.footer);
         styles.wrapper.iter_mut().for_each(remove_attributes);
     }
 
@@ -45,16 +95,76 @@ fn remove_attributes(style: &mut Style) {
     style.is_reverse = false;
 }
 
-fn render_line(line: OutputString, styles: &OutputStyles) {
-    let grouped = line.get_grouped();
-    let mut styled_strings: Vec<AnsiString> = Vec::new();
-    for group in grouped {
-        let style = get_style(group.1, &styles);
-        styled_strings.push(style.paint(group.0))
+fn render_line<W: Write + ?Sized>(
+    line: OutputString,
+    styles: &OutputStyles,
+    painter: &mut Painter<W>,
+) -> io::Result<()> {
+    for (text, c_type) in line.get_grouped() {
+        painter.paint(&text, get_style(c_type, styles))?;
+    }
+    painter.end_line()
+}
+
+/// Writes styled text while keeping track of the terminal's current style, so that only the
+/// changes between one span and the next are written rather than a full set and reset for each.
+struct Painter<'a, W: Write + ?Sized> {
+    writer: &'a mut W,
+    current: Style,
+    /// Line breaks are held back until there's more to write, so the final reset comes before
+    /// the last one rather than dangling after it
+    newline: bool,
+}
+
+impl<'a, W: Write + ?Sized> Painter<'a, W> {
+    fn new(writer: &'a mut W) -> Self {
+        Self {
+            writer,
+            current: Style::default(),
+            newline: false,
+        }
+    }
+
+    fn paint(&mut self, text: &str, style: Style) -> io::Result<()> {
+        self.write_newline()?;
+        // Spaces look the same in any style which doesn't draw anything behind or through them
+        let invisible = text.chars().all(|c| c == ' ')
+            && !shows_on_spaces(style)
+            && !shows_on_spaces(self.current);
+        if !invisible {
+            write!(self.writer, "{}", self.current.infix(style))?;
+            self.current = style;
+        }
+        self.writer.write_all(text.as_bytes())
+    }
+
+    fn end_line(&mut self) -> io::Result<()> {
+        self.write_newline()?;
+        // Terminals fill new lines with the current background, so it can't carry over
+        if shows_on_spaces(self.current) {
+            write!(self.writer, "{}", self.current.suffix())?;
+            self.current = Style::default();
+        }
+        self.newline = true;
+        Ok(())
+    }
+
+    fn write_newline(&mut self) -> io::Result<()> {
+        if self.newline {
+            writeln!(self.writer)?;
+            self.newline = false;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        write!(self.writer, "{}", self.current.suffix())?;
+        self.write_newline()
     }
+}
 
-    styled_strings.iter().for_each(|s| print!("{}", s));
-    print!("\n")
+fn shows_on_spaces(style: Style) -> bool {
+    style.background.is_some() || style.is_reverse || style.is_underline || style.is_strikethrough
 }
 
 fn get_style(c_type: OutputCharType, styles: &OutputStyles) -> Style {
@@ -63,6 +173,91 @@ fn get_style(c_type: OutputCharType, styles: &OutputStyles) -> Style {
         OutputCharType::Padding => styles.padding,
         OutputCharType::Content => styles.content,
         OutputCharType::Author => styles.author,
-        OutputCharType::Wrapper(i) => styles.wrapper[i],
+        OutputCharType::Title => styles.title,
+        OutputCharType::Footer => styles.footer,
+        OutputCharType::Wrapper(i) => styles.wrapper.get(i).copied().unwrap_or_default(),
+        OutputCharType::Margin => Style::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StyleConfig;
+    use crate::display::Centering;
+    use crate::quotes::Quote;
+    use nu_ansi_term::Color;
+
+    fn output(color: bool) -> Output {
+        let quote = Quote::new("Rendered in a terminal".to_owned(), "Someone".to_owned());
+        let mut output = Output::new(color, color, Centering::None, None);
+        output.make_output(&quote, &StyleConfig::default());
+        output
+    }
+
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_render_to_string() {
+        let plain = render_to_string(output(false));
+        assert!(!plain.contains('\x1b'));
+        assert_eq!(plain.lines().count(), 8);
+        assert!(plain.lines().nth(3).unwrap().contains("Rendered"));
+        assert!(render_to_string(output(true)).contains("\x1b["));
+    }
+
+    #[test]
+    fn test_render_minimises_escapes() {
+        let mut output = output(true);
+        let styles = output.styles.as_mut().unwrap();
+        styles.border = Style::new().fg(Color::Blue);
+        styles.content = Style::new().fg(Color::Blue);
+        styles.author = Style::new().fg(Color::Blue).bold();
+        let rendered = render_to_string(output);
+
+        // Blue once, bold added for the author, a reset and blue again to drop the bold for the
+        // border after it, then a reset at the very end
+        assert_eq!(rendered.matches('\x1b').count(), 5);
+        assert!(rendered.starts_with("\x1b[34m┌"));
+        assert!(rendered.ends_with("┘\x1b[0m\n"));
+    }
+
+    #[test]
+    fn test_painter_resets_backgrounds() {
+        let mut written = Vec::new();
+        let mut painter = Painter::new(&mut written);
+        let style = Style::new().on(Color::Red);
+        painter.paint("a", style).unwrap();
+        painter.paint(" ", Style::default()).unwrap();
+        painter.end_line().unwrap();
+        painter.paint("b", Style::default()).unwrap();
+        painter.finish().unwrap();
+
+        assert_eq!(String::from_utf8(written).unwrap(), "\x1b[41ma\x1b[0m \nb");
+    }
+
+    #[test]
+    fn test_render_propagates_errors() {
+        let err = render(output(false), &mut BrokenPipe).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn test_write_atomically() {
+        let path = std::env::temp_dir().join(format!("inspire-motd-{}", process::id()));
+        write_atomically(&path, b"old").unwrap();
+        write_atomically(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -1,54 +1,441 @@
-use clap::{builder::PossibleValue, ValueEnum};
-use nu_ansi_term::AnsiString;
+use std::{fmt::Display, path::PathBuf};
 
-use crate::{config::StyleConfig, display::Output};
+use crate::{
+    config::{BubbleStyle, Config, StyleConfig, TailDirection},
+    display::{Output, OutputCharType, OutputString},
+};
 
-#[derive(Clone, Copy)]
+use cow::Cow;
+use template::Template;
+
+pub mod cow;
+pub mod template;
+
+/// A cat holding the output up from below like a sign, with its arms reaching out to the
+/// sign's edges
+const CAT_HEAD: &str = r"/\_/\";
+const CAT_FACE: &str = "( o.o )";
+const CAT_PAWS: &str = r#"(")_(")"#;
+/// The width of the cat with its arms against its face, which narrower signs sit on
+const CAT_WIDTH: usize = 11;
+/// The cat's outline is painted with its first wrapper style and its eyes with the second
+const CAT_EYES: char = 'o';
+/// The wrapper styles used by the built in wrappers and cows
+const BUILT_IN_STYLES: usize = 2;
+/// What the tail of a thought bubble is made of
+const THOUGHT: char = 'o';
+
+#[derive(Clone)]
 pub enum Wrappers {
     CatSign,
+    /// A cowsay speech bubble
+    Say,
+    /// A cowsay thought bubble
+    Think,
+    Cow(Cow),
+    Template(Template),
 }
 
-impl ValueEnum for Wrappers {
-    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+impl Wrappers {
+    /// Looks up a wrapper defined in the config, then a built in one, and otherwise a cowfile.
+    pub fn find(name: &str, config: &Config) -> Result<Self, WrapperError> {
+        if let Some(wrapper) = config.get_wrapper(name) {
+            return Template::new(wrapper)
+                .map(Wrappers::Template)
+                .map_err(|e| WrapperError::Template(name.to_owned(), e));
+        }
+
+        match name {
+            "catsign" => Ok(Wrappers::CatSign),
+            "say" => Ok(Wrappers::Say),
+            "think" => Ok(Wrappers::Think),
+            _ => {
+                let path = Cow::find(name).ok_or(WrapperError::NotFound(name.to_owned()))?;
+                Ok(Wrappers::Cow(Cow::read(&path)?))
+            }
+        }
+    }
+
+    /// Wraps the output, using the wrapper styles from `first` onwards for its art.
+    pub fn wrap(&self, output: Output, style: &StyleConfig, first: usize) -> Output {
         match self {
-            Wrappers::CatSign => Some(PossibleValue::new("catsign")),
+            Wrappers::CatSign => cat_sign(output, first),
+            Wrappers::Say => bubble(output, false, &style.bubble, first),
+            Wrappers::Think => bubble(output, true, &style.bubble, first),
+            Wrappers::Cow(cow) => cowsay(output, cow, first),
+            Wrappers::Template(template) => template.wrap(output, first),
         }
     }
 
-    fn value_variants<'a>() -> &'a [Self] {
-        &[Self::CatSign]
+    /// How many columns the art adds beside the output. Art above or below it only widens
+    /// output which is narrower than the art, so it adds none.
+    pub fn added_width(&self) -> usize {
+        match self {
+            // The bubble's edge and a space either side
+            Wrappers::Say | Wrappers::Think => 4,
+            Wrappers::CatSign | Wrappers::Cow(_) => 0,
+            Wrappers::Template(template) => template.added_width(),
+        }
+    }
+
+    /// How many wrapper styles the art uses.
+    pub fn styles(&self) -> usize {
+        match self {
+            Wrappers::CatSign | Wrappers::Say | Wrappers::Think | Wrappers::Cow(_) => {
+                BUILT_IN_STYLES
+            }
+            Wrappers::Template(template) => template.styles(),
+        }
     }
 }
 
-impl Wrappers {
-    pub fn wrap(&self, output: Output, config: &StyleConfig) -> Output {
+/// Wraps the output in each wrapper in turn, from the innermost out. Each layer takes the next
+/// wrapper styles after those of the layers inside it.
+pub fn wrap_all(wrappers: &[Wrappers], mut output: Output, style: &StyleConfig) -> Output {
+    let mut first = 0;
+    for wrapper in wrappers {
+        output = wrapper.wrap(output, style, first);
+        first += wrapper.styles();
+    }
+    output
+}
+
+/// How many columns all of the wrappers add beside the output.
+pub fn added_width(wrappers: &[Wrappers]) -> usize {
+    wrappers.iter().map(Wrappers::added_width).sum()
+}
+
+#[derive(Debug)]
+pub enum WrapperError {
+    NotFound(String),
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, String),
+    Template(String, String),
+}
+
+impl Display for WrapperError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Wrappers::CatSign => todo!(),
+            WrapperError::NotFound(name) => write!(f, "no wrapper or cow named '{}'", name),
+            WrapperError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            WrapperError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            WrapperError::Template(name, e) => write!(f, "wrapper '{}': {}", name, e),
         }
     }
 }
 
-fn default_wrapper(output: Output, config: &StyleConfig) -> Output {
-    return output;
+impl std::error::Error for WrapperError {}
+
+fn cat_sign(mut output: Output, first: usize) -> Output {
+    let sign_width = output.width();
+    let width = sign_width.max(CAT_WIDTH);
+
+    // A sign narrower than the cat is centered on it
+    for line in &mut output.output {
+        indent(line, (width - sign_width) / 2);
+    }
+
+    // The arms go up to the sign's corners, with the rest of the cat centered between them
+    let mut rows = vec![vec![' '; width]; 3];
+    rows[0][0] = '\\';
+    rows[0][width - 1] = '/';
+    place(&mut rows[0], CAT_HEAD);
+    rows[1][1] = '\\';
+    rows[1][2..width - 2].fill('_');
+    rows[1][width - 2] = '/';
+    place(&mut rows[1], CAT_FACE);
+    place(&mut rows[2], CAT_PAWS);
+
+    for row in rows {
+        let row: String = row.into_iter().collect();
+        let art = row.trim_start();
+        let mut line = OutputString::default();
+        indent(&mut line, row.len() - art.len());
+        for c in art.trim_end().chars() {
+            let char_type = match c {
+                CAT_EYES => OutputCharType::Wrapper(first + 1),
+                _ => OutputCharType::Wrapper(first),
+            };
+            line.push(c, char_type);
+        }
+        output.output.push(line);
+    }
+
+    output
+}
+
+/// Puts part of the cat in the middle of a row.
+fn place(row: &mut [char], part: &str) {
+    let start = (row.len() - part.len()) / 2;
+    for (cell, c) in row[start..].iter_mut().zip(part.chars()) {
+        *cell = c;
+    }
 }
 
-fn border<'a>(quote_output: Vec<AnsiString<'a>>, config: &StyleConfig) -> Vec<AnsiString<'a>> {
-    let output_width = quote_output
-        .iter()
-        .fold(0, |len, line| return len.max(line.as_str().len()));
-    let corners = config.border.chars.corners;
-    let vertical = config.border.chars.vertical;
-    let horizontal = config.border.chars.horizontal;
-    let first_line = String::from(
-        corners[0].to_string()
-            + &horizontal.to_string().repeat(output_width)
-            + &corners[1].to_string(),
-    );
-    let last_line = String::from(
-        corners[2].to_string()
-            + &horizontal.to_string().repeat(output_width)
-            + &corners[3].to_string(),
-    );
+/// Draws a cowsay bubble around the output, whose edges follow how many lines it has, with a
+/// tail leading away below it. The bubble uses the first of its wrapper styles and the tail the
+/// second.
+fn bubble(mut output: Output, think: bool, style: &BubbleStyle, first: usize) -> Output {
+    output.pad_lines();
+    let width = output.width();
+    let count = output.output.len();
+    let edge = OutputCharType::Wrapper(first);
+
+    let mut lines = Vec::with_capacity(count + 2 + style.tail_length);
+    let mut top = OutputString::default();
+    indent(&mut top, 1);
+    top.push_str(&"_".repeat(width + 2), edge);
+    lines.push(top);
+
+    for (i, content) in output.output.iter().enumerate() {
+        let (left, right) = match i {
+            _ if think => ('(', ')'),
+            _ if count == 1 => ('<', '>'),
+            0 => ('/', '\\'),
+            i if i == count - 1 => ('\\', '/'),
+            _ => ('|', '|'),
+        };
+        let mut line = OutputString::default();
+        line.push(left, edge);
+        line.push(' ', edge);
+        line.append(content);
+        line.push(' ', edge);
+        line.push(right, edge);
+        lines.push(line);
+    }
+
+    let mut bottom = OutputString::default();
+    indent(&mut bottom, 1);
+    bottom.push_str(&"-".repeat(width + 2), edge);
+    lines.push(bottom);
 
-    return quote_output;
+    // The tail starts under the bubble, even when asked to start further along
+    let start = style.tail_position.min(width + 3);
+    for row in 0..style.tail_length {
+        let (column, c) = match style.tail_direction {
+            TailDirection::Left => (start.saturating_sub(row), '/'),
+            TailDirection::Right => (start + row, '\\'),
+            TailDirection::Down => (start, '|'),
+        };
+        let mut line = OutputString::default();
+        indent(&mut line, column);
+        line.push(
+            if think { THOUGHT } else { c },
+            OutputCharType::Wrapper(first + 1),
+        );
+        lines.push(line);
+    }
+
+    output.output = lines;
+    output
+}
+
+/// Draws the cow below the output, which becomes its speech bubble. The cow's thoughts lead up
+/// from it towards the box, as the tail of the bubble.
+fn cowsay(mut output: Output, cow: &Cow, first: usize) -> Output {
+    output.output.extend(cow.draw(first));
+    output
+}
+
+fn indent(line: &mut OutputString, width: usize) {
+    for _ in 0..width {
+        line.push_front(' ', OutputCharType::Margin);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::StyleConfig, display::Centering, quotes::Quote};
+
+    #[test]
+    fn test_cat_sign() {
+        let style = StyleConfig::default();
+        let quote = Quote::new(
+            "Meow, said the cat to the sign".to_owned(),
+            "Cat".to_owned(),
+        );
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.make_output(&quote, &style);
+        let sign = output.output.clone();
+
+        let wrapped = Wrappers::CatSign.wrap(output, &style, 0);
+        assert_eq!(wrapped.output.len(), sign.len() + 3);
+        assert!(wrapped.output[..sign.len()] == sign[..]);
+        assert!(wrapped.output[sign.len() + 1]
+            .get_grouped()
+            .contains(&("o".to_owned(), OutputCharType::Wrapper(1))));
+    }
+
+    #[test]
+    fn test_cat_arms_reach_the_sign() {
+        let cat = |width: usize| -> Vec<String> {
+            let mut output = Output::new(false, false, Centering::None, None);
+            output.output = vec![OutputString::from_str(
+                "x".repeat(width),
+                OutputCharType::Content,
+            )];
+            let wrapped = Wrappers::CatSign.wrap(output, &StyleConfig::default(), 0);
+            wrapped.output[1..].iter().map(OutputString::text).collect()
+        };
+
+        // At its narrowest the arms hug the cat's face
+        assert_eq!(cat(11), [r"\  /\_/\  /", r" \( o.o )/", r#"  (")_(")"#]);
+        assert_eq!(
+            cat(16),
+            [r"\    /\_/\     /", r" \__( o.o )___/", r#"    (")_(")"#]
+        );
+        for width in [20, 41] {
+            let cat = cat(width);
+            assert_eq!(cat[0].len(), width);
+            assert!(cat[0].starts_with('\\') && cat[0].ends_with('/'));
+            assert_eq!(cat[1].len(), width - 1);
+        }
+    }
+
+    #[test]
+    fn test_cowsay() {
+        let style = StyleConfig::default();
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.make_output(&Quote::new("Moo".to_owned(), String::new()), &style);
+        let bubble = output.output.clone();
+
+        let cow = Cow::parse("$the_cow = <<EOC;\n  $thoughts\n  ($eyes)\nEOC\n").unwrap();
+        let wrapped = Wrappers::Cow(cow).wrap(output, &style, 0);
+        assert!(wrapped.output[..bubble.len()] == bubble[..]);
+        let cow: Vec<String> = wrapped.output[bubble.len()..]
+            .iter()
+            .map(OutputString::text)
+            .collect();
+        assert_eq!(cow, vec![r"  \", "  (oo)"]);
+    }
+
+    #[test]
+    fn test_wrappers_fit_terminal() {
+        let style = StyleConfig::default();
+        let quote = Quote::new("Moo ".repeat(40), "Cow".to_owned());
+        let wrappers = [Wrappers::Say, Wrappers::CatSign];
+        let mut output = Output::new(false, false, Centering::None, Some((40, 24)));
+        output.wrapped_width = added_width(&wrappers);
+        output.make_output(&quote, &style);
+
+        let wrapped = wrap_all(&wrappers, output, &style);
+        assert!(wrapped.width() <= 40, "{}", wrapped.width());
+    }
+
+    fn bubble_lines(wrapper: Wrappers, lines: &[&str], style: &StyleConfig) -> Vec<String> {
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.output = lines
+            .iter()
+            .map(|l| OutputString::from_str(l, OutputCharType::Content))
+            .collect();
+        wrapper
+            .wrap(output, style, 0)
+            .output
+            .iter()
+            .map(OutputString::text)
+            .collect()
+    }
+
+    #[test]
+    fn test_say() {
+        let style = StyleConfig::default();
+        // The tail is kept under a bubble which is narrower than its position
+        assert_eq!(
+            bubble_lines(Wrappers::Say, &["Moo"], &style),
+            vec![" _____", "< Moo >", " -----", "      \\", "       \\"]
+        );
+        let lines = bubble_lines(Wrappers::Say, &["One", "Two", "Three"], &style);
+        assert_eq!(lines[1..4], ["/ One   \\", "| Two   |", "\\ Three /"]);
+    }
+
+    #[test]
+    fn test_think() {
+        let style = StyleConfig {
+            bubble: BubbleStyle {
+                tail_position: 2,
+                tail_direction: TailDirection::Left,
+                tail_length: 3,
+            },
+            ..StyleConfig::default()
+        };
+        let lines = bubble_lines(Wrappers::Think, &["Hmm", "Yes"], &style);
+        assert_eq!(lines[1..3], ["( Hmm )", "( Yes )"]);
+        assert_eq!(lines[4..], ["  o", " o", "o"]);
+    }
+
+    #[test]
+    fn test_wrap_all() {
+        let config: Config =
+            toml::from_str("[[wrappers]]\nname = \"frame\"\nart = \"#@#\"\nregions = \"2\"\n")
+                .unwrap();
+        let wrappers = [Wrappers::CatSign, Wrappers::find("frame", &config).unwrap()];
+        assert_eq!(wrappers[1].styles(), 3);
+
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.make_output(
+            &Quote::new("Meow".to_owned(), String::new()),
+            &StyleConfig::default(),
+        );
+        let inner = Wrappers::CatSign.wrap(output, &StyleConfig::default(), 0);
+        let height = inner.output.len();
+        let width = inner.width();
+
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.make_output(
+            &Quote::new("Meow".to_owned(), String::new()),
+            &StyleConfig::default(),
+        );
+        let wrapped = wrap_all(&wrappers, output, &StyleConfig::default());
+        assert_eq!(wrapped.output.len(), height);
+        assert_eq!(wrapped.width(), width + 2);
+        // The frame's styles come after the two used by the cat
+        let last = wrapped.output[height - 1].get_grouped();
+        assert!(last[0] == ("#".to_owned(), OutputCharType::Wrapper(4)));
+        assert!(wrapped.output[height - 2]
+            .get_grouped()
+            .contains(&("o".to_owned(), OutputCharType::Wrapper(1))));
+    }
+
+    #[test]
+    fn test_find() {
+        let config: Config = toml::from_str(
+            "[[wrappers]]\nname = \"catsign\"\nart = \"[@]\"\n\n\
+             [[wrappers]]\nname = \"broken\"\nart = \"[]\"\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            Wrappers::find("catsign", &Config::default()),
+            Ok(Wrappers::CatSign)
+        ));
+        assert!(matches!(
+            Wrappers::find("catsign", &config),
+            Ok(Wrappers::Template(_))
+        ));
+        assert!(matches!(
+            Wrappers::find("broken", &config),
+            Err(WrapperError::Template(..))
+        ));
+        assert!(matches!(
+            Wrappers::find("no-such-cow", &config),
+            Err(WrapperError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_narrow_sign_is_centered_on_the_cat() {
+        let style = StyleConfig {
+            padding: (0, 0),
+            ..StyleConfig::default()
+        };
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.make_output(&Quote::new("Hi".to_owned(), String::new()), &style);
+        let width = output.width();
+
+        let wrapped = Wrappers::CatSign.wrap(output, &style, 0);
+        let indent = (CAT_WIDTH - width) / 2;
+        assert!(wrapped.output[0]
+            .text()
+            .starts_with(&(" ".repeat(indent) + "┌")));
+    }
 }
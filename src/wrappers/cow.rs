@@ -0,0 +1,300 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::display::{OutputCharType, OutputString};
+
+use super::WrapperError;
+
+/// The environment variable cowsay reads its search path from
+const COWPATH_ENV: &str = "COWPATH";
+/// Where cowsay installs its cows, which differs between distributions
+const COW_DIRS: [&str; 3] = [
+    "/usr/share/cowsay/cows",
+    "/usr/share/cows",
+    "/usr/local/share/cows",
+];
+const COW_EXTENSION: &str = "cow";
+
+const DEFAULT_EYES: &str = "oo";
+const DEFAULT_TONGUE: &str = "  ";
+/// The tail leading from the cow up to what it's saying
+const SAY_THOUGHTS: &str = "\\";
+
+/// Part of a line of a cow, before its variables are substituted.
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+/// A cowsay cowfile, drawn below the output as though the cow were saying it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cow {
+    lines: Vec<Vec<Segment>>,
+    /// Variables the cowfile assigns before its heredoc, which can replace the eyes or tongue
+    variables: HashMap<String, String>,
+}
+
+impl Cow {
+    /// Finds a cowfile by path, or by name in `COWPATH` and then the usual install locations.
+    pub fn find(name: &str) -> Option<PathBuf> {
+        let path = PathBuf::from(name);
+        if path.is_file() {
+            return Some(path);
+        }
+
+        let mut dirs: Vec<PathBuf> = env::var_os(COWPATH_ENV)
+            .map(|p| env::split_paths(&p).collect())
+            .unwrap_or_default();
+        dirs.extend(dirs::data_dir().map(|d| d.join("inspire").join("cows")));
+        dirs.extend(COW_DIRS.iter().map(PathBuf::from));
+        find_in(name, &dirs)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, WrapperError> {
+        let content =
+            fs::read_to_string(path).map_err(|e| WrapperError::Io(path.to_path_buf(), e))?;
+        Self::parse(&content).map_err(|e| WrapperError::Parse(path.to_path_buf(), e))
+    }
+
+    /// Parses the `$the_cow` heredoc out of a cowfile. Cowfiles are Perl, but all that's
+    /// understood here are string assignments and the heredoc itself.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut variables = HashMap::new();
+        let mut lines = content.lines().map(|l| l.trim_end_matches('\r'));
+
+        let (terminator, interpolate) = loop {
+            let Some(line) = lines.next() else {
+                return Err("there is no $the_cow heredoc".to_owned());
+            };
+            let line = line.trim();
+            if let Some(heredoc) = line
+                .strip_prefix("$the_cow")
+                .map(str::trim_start)
+                .and_then(|l| l.strip_prefix('='))
+                .map(str::trim_start)
+                .and_then(|l| l.strip_prefix("<<"))
+            {
+                break parse_terminator(heredoc)?;
+            }
+            if let Some((name, value)) = parse_assignment(line) {
+                variables.insert(name, value);
+            }
+        };
+
+        let mut cow = Vec::new();
+        for line in lines.by_ref() {
+            if line.trim_end() == terminator {
+                return Ok(Self {
+                    lines: cow,
+                    variables,
+                });
+            }
+            cow.push(match interpolate {
+                true => parse_line(line),
+                false => vec![Segment::Text(line.to_owned())],
+            });
+        }
+        Err(format!("the heredoc is never ended with {}", terminator))
+    }
+
+    /// Draws the cow with its variables substituted. Its eyes and tongue use the wrapper style
+    /// after `first` and everything else `first` itself.
+    pub fn draw(&self, first: usize) -> Vec<OutputString> {
+        self.lines
+            .iter()
+            .map(|segments| {
+                let mut line = OutputString::default();
+                for segment in segments {
+                    let (text, char_type) = match segment {
+                        Segment::Text(text) => (text.as_str(), OutputCharType::Wrapper(first)),
+                        Segment::Variable(name) => {
+                            let char_type = match name.as_str() {
+                                "eyes" | "tongue" => OutputCharType::Wrapper(first + 1),
+                                _ => OutputCharType::Wrapper(first),
+                            };
+                            (self.variable(name), char_type)
+                        }
+                    };
+                    line.push_str(text, char_type);
+                }
+                line
+            })
+            .collect()
+    }
+
+    fn variable(&self, name: &str) -> &str {
+        if let Some(value) = self.variables.get(name) {
+            return value;
+        }
+        match name {
+            "eyes" => DEFAULT_EYES,
+            "tongue" => DEFAULT_TONGUE,
+            "thoughts" => SAY_THOUGHTS,
+            // Perl interpolates anything undefined as nothing
+            _ => "",
+        }
+    }
+}
+
+fn find_in(name: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let file = Path::new(name).with_extension(COW_EXTENSION);
+    dirs.iter().map(|d| d.join(&file)).find(|p| p.is_file())
+}
+
+/// Reads the terminator of a heredoc, and whether its content is interpolated, from what
+/// follows `<<`, such as `"EOC";` or `'EOC';`.
+fn parse_terminator(heredoc: &str) -> Result<(String, bool), String> {
+    let heredoc = heredoc.trim().trim_end_matches(';').trim_end();
+    let (terminator, interpolate) = match heredoc.chars().next() {
+        Some(quote @ ('"' | '\'')) => (
+            heredoc[1..].strip_suffix(quote).unwrap_or(&heredoc[1..]),
+            quote == '"',
+        ),
+        _ => (heredoc, true),
+    };
+    if terminator.is_empty() {
+        return Err("the heredoc has no terminator".to_owned());
+    }
+    Ok((terminator.to_owned(), interpolate))
+}
+
+/// Parses a simple string assignment such as `$eyes = "..";`.
+fn parse_assignment(line: &str) -> Option<(String, String)> {
+    let (name, value) = line.strip_prefix('$')?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    let value = value.trim().strip_suffix(';')?.trim_end();
+    let value = match value.chars().next()? {
+        '"' => unescape(value.strip_prefix('"')?.strip_suffix('"')?),
+        '\'' => value.strip_prefix('\'')?.strip_suffix('\'')?.to_owned(),
+        _ => return None,
+    };
+    Some((name.to_owned(), value))
+}
+
+fn unescape(text: &str) -> String {
+    parse_line(text)
+        .into_iter()
+        .map(|s| match s {
+            Segment::Text(text) => text,
+            Segment::Variable(_) => String::new(),
+        })
+        .collect()
+}
+
+/// Splits a line of an interpolated heredoc into text and variables. A backslash escapes the
+/// character after it, as Perl does for characters without a special meaning.
+fn parse_line(line: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.push(chars.next().unwrap_or('\\')),
+            '$' => {
+                let braced = chars.next_if_eq(&'{').is_some();
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                if braced {
+                    chars.next_if_eq(&'}');
+                }
+
+                if name.is_empty() {
+                    text.push('$');
+                    continue;
+                }
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Variable(name));
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT_COW: &str = r#"$the_cow = <<"EOC";
+        $thoughts   ^__^
+         $thoughts  ($eyes)\\_______
+            (__)\\       )\\/\\
+             $tongue ||----w |
+                ||     ||
+EOC
+"#;
+
+    #[test]
+    fn test_parse_default_cow() {
+        let cow = Cow::parse(DEFAULT_COW).unwrap();
+        let lines: Vec<String> = cow.draw(0).iter().map(OutputString::text).collect();
+        assert_eq!(
+            lines,
+            vec![
+                r"        \   ^__^",
+                r"         \  (oo)\_______",
+                r"            (__)\       )\/\",
+                r"                ||----w |",
+                r"                ||     ||",
+            ]
+        );
+        assert!(cow.draw(0)[1]
+            .get_grouped()
+            .contains(&("oo".to_owned(), OutputCharType::Wrapper(1))));
+    }
+
+    #[test]
+    fn test_parse_assignments() {
+        let content = "## A comment\n$eyes = \"\\$\\$\";\n$extra = 'x';\n\
+                       $the_cow = <<EOC;\n$eyes ${extra}$undefined \\@\nEOC\n";
+        let cow = Cow::parse(content).unwrap();
+        assert_eq!(cow.draw(0)[0].text(), "$$ x @");
+    }
+
+    #[test]
+    fn test_parse_uninterpolated() {
+        let cow = Cow::parse("$the_cow = << 'EOC';\n$eyes \\\nEOC\n").unwrap();
+        assert_eq!(cow.draw(0)[0].text(), "$eyes \\");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Cow::parse("print 'moo';\n").is_err());
+        assert!(Cow::parse("$the_cow = <<EOC;\n  ^__^\n").is_err());
+    }
+
+    #[test]
+    fn test_find_in() {
+        let dir = env::temp_dir().join(format!("inspire-cows-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("moose.cow"), DEFAULT_COW).unwrap();
+
+        let missing = dir.join("missing");
+        assert_eq!(
+            find_in("moose", &[missing.clone(), dir.clone()]),
+            Some(dir.join("moose.cow"))
+        );
+        assert_eq!(
+            find_in("moose.cow", std::slice::from_ref(&dir)),
+            Some(dir.join("moose.cow"))
+        );
+        assert_eq!(find_in("bunny", &[missing, dir.clone()]), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,427 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    config::{Anchor, WrapperConfig},
+    display::{Output, OutputCharType, OutputString},
+};
+
+type Cell = (String, OutputCharType);
+
+/// The rectangle of the art which the box replaces.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Placeholder {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+/// Ascii art from the config, stretched to fit the box and drawn around it. Each character of
+/// the art is a column, so art should avoid wide characters.
+#[derive(Clone)]
+pub struct Template {
+    /// The art as a grid, with short rows filled out with margin
+    cells: Vec<Vec<Cell>>,
+    anchor: Anchor,
+    placeholder: Option<Placeholder>,
+    stretch_rows: Vec<usize>,
+    stretch_columns: Vec<usize>,
+    /// How many wrapper styles the regions use
+    styles: usize,
+}
+
+impl Template {
+    pub fn new(config: &WrapperConfig) -> Result<Self, String> {
+        let art: Vec<Vec<&str>> = config
+            .art
+            .lines()
+            .map(|l| l.graphemes(true).collect())
+            .collect();
+        let regions: Vec<Vec<&str>> = config
+            .regions
+            .as_deref()
+            .unwrap_or_default()
+            .lines()
+            .map(|l| l.graphemes(true).collect())
+            .collect();
+        let width = art.iter().map(Vec::len).max().unwrap_or(0);
+        if width == 0 {
+            return Err("the art is empty".to_owned());
+        }
+
+        let placeholder = config.placeholder.to_string();
+        // The corners of the placeholder, top left then bottom right
+        let mut corners: Option<((usize, usize), (usize, usize))> = None;
+        let mut styles = 1;
+        let mut cells = Vec::new();
+        for (y, row) in art.iter().enumerate() {
+            let mut line = Vec::new();
+            for x in 0..width {
+                let Some(grapheme) = row.get(x) else {
+                    line.push((" ".to_owned(), OutputCharType::Margin));
+                    continue;
+                };
+                if *grapheme == placeholder {
+                    corners = Some(match corners {
+                        Some((start, end)) => ((start.0.min(x), start.1), (end.0.max(x), y)),
+                        None => ((x, y), (x, y)),
+                    });
+                }
+                let region = regions
+                    .get(y)
+                    .and_then(|r| r.get(x))
+                    .and_then(|r| r.parse().ok())
+                    .unwrap_or(0);
+                styles = styles.max(region + 1);
+                line.push((grapheme.to_string(), OutputCharType::Wrapper(region)));
+            }
+            cells.push(line);
+        }
+
+        if let Some(row) = config.stretch_rows.iter().find(|r| **r >= cells.len()) {
+            return Err(format!("stretch row {} is outside the art", row));
+        }
+        if let Some(column) = config.stretch_columns.iter().find(|c| **c >= width) {
+            return Err(format!("stretch column {} is outside the art", column));
+        }
+
+        let placeholder = corners.map(|(start, end)| Placeholder {
+            x: start.0,
+            y: start.1,
+            width: end.0 - start.0 + 1,
+            height: end.1 - start.1 + 1,
+        });
+        if config.anchor == Anchor::Around && placeholder.is_none() {
+            return Err(format!(
+                "the art has no '{}' placeholder for the box",
+                config.placeholder
+            ));
+        }
+
+        Ok(Self {
+            cells,
+            anchor: config.anchor,
+            placeholder,
+            stretch_rows: config.stretch_rows.clone(),
+            stretch_columns: config.stretch_columns.clone(),
+            styles,
+        })
+    }
+
+    /// Draws the art around the output, using the wrapper styles from `first` onwards.
+    pub fn wrap(&self, mut output: Output, first: usize) -> Output {
+        output.pad_lines();
+        output.output = match (self.anchor, self.placeholder) {
+            (Anchor::Around, Some(placeholder)) => self.around(&output, placeholder, first),
+            (Anchor::Left | Anchor::Right, _) => self.beside(&output, first),
+            _ => self.stacked(&output, first),
+        };
+        output
+    }
+
+    pub fn styles(&self) -> usize {
+        self.styles
+    }
+
+    fn width(&self) -> usize {
+        self.cells[0].len()
+    }
+
+    /// How many columns the art adds beside the box, which is the art outside the placeholder
+    /// or all of it when it goes to one side.
+    pub fn added_width(&self) -> usize {
+        match (self.anchor, self.placeholder) {
+            (Anchor::Around, Some(placeholder)) => self.width() - placeholder.width,
+            (Anchor::Left | Anchor::Right, _) => self.width(),
+            _ => 0,
+        }
+    }
+
+    /// Puts the box in the placeholder, stretching the art until the placeholder is big enough.
+    /// Only marked rows and columns which cross the placeholder help with that, and when there
+    /// are none the middle of the placeholder is stretched instead.
+    fn around(&self, output: &Output, placeholder: Placeholder, first: usize) -> Vec<OutputString> {
+        let (width, height) = (output.width(), output.output.len());
+        let columns = repeats(
+            self.width(),
+            &marked_within(&self.stretch_columns, placeholder.x, placeholder.width),
+            width.saturating_sub(placeholder.width),
+        );
+        let rows = repeats(
+            self.cells.len(),
+            &marked_within(&self.stretch_rows, placeholder.y, placeholder.height),
+            height.saturating_sub(placeholder.height),
+        );
+        let art = self.stretched(&columns, &rows, first);
+
+        let x: usize = columns[..placeholder.x].iter().sum();
+        let y: usize = rows[..placeholder.y].iter().sum();
+        let inner_width: usize = columns[placeholder.x..][..placeholder.width].iter().sum();
+        let inner_height: usize = rows[placeholder.y..][..placeholder.height].iter().sum();
+        // A box smaller than the placeholder is centered in it
+        let left = (inner_width - width) / 2;
+        let top = (inner_height - height) / 2;
+
+        art.iter()
+            .enumerate()
+            .map(|(i, row)| {
+                if !(y..y + inner_height).contains(&i) {
+                    return to_line(row);
+                }
+                let mut line = to_line(&row[..x]);
+                match (i - y).checked_sub(top).and_then(|r| output.output.get(r)) {
+                    Some(box_line) => {
+                        margin(&mut line, left);
+                        line.append(box_line);
+                        margin(&mut line, inner_width - width - left);
+                    }
+                    None => margin(&mut line, inner_width),
+                }
+                line.append(&to_line(&row[x + inner_width..]));
+                line
+            })
+            .collect()
+    }
+
+    /// Puts the art above or below the box, stretching its marked columns to the box's width.
+    fn stacked(&self, output: &Output, first: usize) -> Vec<OutputString> {
+        let width = output.width();
+        let columns = repeats(
+            self.width(),
+            &self.stretch_columns,
+            width.saturating_sub(self.width()),
+        );
+        let rows = vec![1; self.cells.len()];
+        let art_width: usize = columns.iter().sum();
+
+        // Whichever of the art and the box is narrower is centered on the other
+        let mut art: Vec<OutputString> = self
+            .stretched(&columns, &rows, first)
+            .iter()
+            .map(|r| to_line(r))
+            .collect();
+        for line in &mut art {
+            super::indent(line, width.saturating_sub(art_width) / 2);
+        }
+        let mut lines = output.output.clone();
+        for line in &mut lines {
+            super::indent(line, art_width.saturating_sub(width) / 2);
+        }
+
+        match self.anchor {
+            Anchor::Above => art.append(&mut lines),
+            _ => {
+                lines.append(&mut art);
+                art = lines;
+            }
+        }
+        art
+    }
+
+    /// Puts the art to the left or right of the box, stretching its marked rows to the box's
+    /// height.
+    fn beside(&self, output: &Output, first: usize) -> Vec<OutputString> {
+        let (width, height) = (output.width(), output.output.len());
+        let columns = vec![1; self.width()];
+        let rows = repeats(
+            self.cells.len(),
+            &self.stretch_rows,
+            height.saturating_sub(self.cells.len()),
+        );
+        let art = self.stretched(&columns, &rows, first);
+
+        // Whichever of the art and the box is shorter is centered on the other
+        let art_top = height.saturating_sub(art.len()) / 2;
+        let box_top = art.len().saturating_sub(height) / 2;
+        (0..height.max(art.len()))
+            .map(|i| {
+                let mut art_line = OutputString::default();
+                match i.checked_sub(art_top).and_then(|r| art.get(r)) {
+                    Some(row) => art_line.append(&to_line(row)),
+                    None => margin(&mut art_line, self.width()),
+                }
+                let mut line = OutputString::default();
+                if self.anchor == Anchor::Left {
+                    line.append(&art_line);
+                }
+                match i.checked_sub(box_top).and_then(|r| output.output.get(r)) {
+                    Some(box_line) => line.append(box_line),
+                    None => margin(&mut line, width),
+                }
+                if self.anchor == Anchor::Right {
+                    line.append(&art_line);
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// The art with each column and row repeated the given number of times, and its styles
+    /// moved along to start from `first`.
+    fn stretched(&self, columns: &[usize], rows: &[usize], first: usize) -> Vec<Vec<Cell>> {
+        self.cells
+            .iter()
+            .zip(rows)
+            .flat_map(|(row, &count)| {
+                let row: Vec<Cell> = row
+                    .iter()
+                    .zip(columns)
+                    .flat_map(|((grapheme, char_type), &count)| {
+                        let char_type = match char_type {
+                            OutputCharType::Wrapper(i) => OutputCharType::Wrapper(first + i),
+                            other => *other,
+                        };
+                        std::iter::repeat_n((grapheme.clone(), char_type), count)
+                    })
+                    .collect();
+                std::iter::repeat_n(row, count)
+            })
+            .collect()
+    }
+}
+
+/// How many times to repeat each of `len` rows or columns so that there are `extra` more,
+/// spreading the extra as evenly as possible over the marked ones.
+fn repeats(len: usize, marked: &[usize], extra: usize) -> Vec<usize> {
+    let mut counts = vec![1; len];
+    if !marked.is_empty() {
+        for i in 0..extra {
+            counts[marked[i % marked.len()]] += 1;
+        }
+    }
+    counts
+}
+
+/// The marked rows or columns within a span of the art, or the middle of it if none are.
+fn marked_within(marked: &[usize], start: usize, len: usize) -> Vec<usize> {
+    let within: Vec<usize> = marked
+        .iter()
+        .copied()
+        .filter(|m| (start..start + len).contains(m))
+        .collect();
+    match within.is_empty() {
+        true => vec![start + len / 2],
+        false => within,
+    }
+}
+
+fn to_line(cells: &[Cell]) -> OutputString {
+    let mut line = OutputString::default();
+    for (grapheme, char_type) in cells {
+        line.push_str(grapheme, *char_type);
+    }
+    line
+}
+
+fn margin(line: &mut OutputString, width: usize) {
+    for _ in 0..width {
+        line.push(' ', OutputCharType::Margin);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Centering;
+
+    /// A box around the text, drawn by hand so that the tests don't depend on the layout.
+    fn output(text: &str) -> Output {
+        let edge = "─".repeat(text.len());
+        let mut output = Output::new(false, false, Centering::None, None);
+        output.output = [
+            format!("┌{}┐", edge),
+            format!("│{}│", text),
+            format!("└{}┘", edge),
+        ]
+        .iter()
+        .map(|l| OutputString::from_str(l, OutputCharType::Border))
+        .collect();
+        output
+    }
+
+    fn template(art: &str, anchor: Anchor, rows: &[usize], columns: &[usize]) -> Template {
+        Template::new(&WrapperConfig {
+            art: art.to_owned(),
+            anchor,
+            regions: Some("1\n".to_owned()),
+            stretch_rows: rows.to_vec(),
+            stretch_columns: columns.to_vec(),
+            ..WrapperConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_around_stretches_to_fit() {
+        let frame = template("*-*\n|@|\n*-*\n", Anchor::Around, &[], &[]);
+        let lines: Vec<String> = frame
+            .wrap(output("Hi"), 0)
+            .output
+            .iter()
+            .map(OutputString::text)
+            .collect();
+        assert_eq!(
+            lines,
+            vec!["*----*", "|┌──┐|", "|│Hi│|", "|└──┘|", "*----*"]
+        );
+    }
+
+    #[test]
+    fn test_around_centers_small_box() {
+        let frame = template("########\n#@@@@@@#\n########\n", Anchor::Around, &[], &[]);
+        let lines: Vec<String> = frame
+            .wrap(output("Hi"), 0)
+            .output
+            .iter()
+            .map(OutputString::text)
+            .collect();
+        assert_eq!(lines[1], "# ┌──┐ #");
+        assert_eq!(lines[3], "# └──┘ #");
+    }
+
+    #[test]
+    fn test_stacked() {
+        let banner = template("<=>\n", Anchor::Above, &[], &[1]);
+        let wrapped = banner.wrap(output("Hello"), 0);
+        assert_eq!(wrapped.output[0].text(), "<=====>");
+        assert!(wrapped.output[0].get_grouped()[0] == ("<".to_owned(), OutputCharType::Wrapper(1)));
+
+        let under = template("^\n", Anchor::Below, &[], &[]);
+        let wrapped = under.wrap(output("Hello"), 0);
+        assert_eq!(wrapped.output.last().unwrap().text(), "   ^");
+    }
+
+    #[test]
+    fn test_beside() {
+        let pole = template("o\n|\n", Anchor::Left, &[1], &[]);
+        let lines: Vec<String> = pole
+            .wrap(output("Hi"), 0)
+            .output
+            .iter()
+            .map(OutputString::text)
+            .collect();
+        assert_eq!(lines, vec!["o┌──┐", "|│Hi│", "|└──┘"]);
+
+        let tall = template("a\nb\nc\nd\ne\n", Anchor::Right, &[], &[]);
+        let lines: Vec<String> = tall
+            .wrap(output("Hi"), 0)
+            .output
+            .iter()
+            .map(OutputString::text)
+            .collect();
+        assert_eq!(lines, vec!["    a", "┌──┐b", "│Hi│c", "└──┘d", "    e"]);
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let config = |art: &str, rows: Vec<usize>| WrapperConfig {
+            art: art.to_owned(),
+            stretch_rows: rows,
+            ..WrapperConfig::default()
+        };
+        assert!(Template::new(&config("", vec![])).is_err());
+        assert!(Template::new(&config("+-+\n", vec![])).is_err());
+        assert!(Template::new(&config("[@]\n", vec![1])).is_err());
+        assert!(Template::new(&config("[@]\n", vec![0])).is_ok());
+    }
+}